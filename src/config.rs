@@ -1,14 +1,33 @@
 use crate::{
-    constants::{ADDRESS, TARGET_DIR},
+    constants::{
+        ADDRESS, BODY_TIMEOUT_SECS, HEADER_TIMEOUT_SECS, KEEP_ALIVE_TIMEOUT_SECS, MAX_BODY_SIZE,
+        MAX_DECODED_BODY_SIZE, MAX_HEADER_COUNT, MAX_HEADER_SIZE, MAX_KEEP_ALIVE_REQUESTS,
+        MAX_REQUEST_LINE_LEN, MIN_DATA_RATE, TARGET_DIR, WRITE_TIMEOUT_SECS,
+    },
     dir::{Dir, FileSystemAccess},
     Result,
 };
 use lexopt::prelude::*;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct Config {
     pub address: String,
     pub directory: Dir,
+    pub keep_alive_timeout: Duration,
+    pub header_timeout: Duration,
+    pub body_timeout: Duration,
+    pub write_timeout: Duration,
+    pub min_data_rate: u64,
+    pub max_requests: usize,
+    pub send_date: bool,
+    pub send_server: bool,
+    pub max_decoded_body: u64,
+    pub max_request_line: usize,
+    pub max_header_size: usize,
+    pub max_headers: usize,
+    pub max_body: u64,
+    pub strict: bool,
 }
 
 impl Config {
@@ -33,8 +52,91 @@ impl Config {
                         }
                     }
                 }
+                Short('k') | Long("keep_alive_timeout") => {
+                    if let Ok(val) = parser.value() {
+                        if let Ok(parsed_val) = val.parse::<u64>() {
+                            // A timeout of 0 can't be set on a socket, and would
+                            // time every request out if it could
+                            config.keep_alive_timeout = Duration::from_secs(parsed_val.max(1));
+                        }
+                    }
+                }
+                Long("header_timeout") => {
+                    if let Ok(val) = parser.value() {
+                        if let Ok(parsed_val) = val.parse::<u64>() {
+                            config.header_timeout = Duration::from_secs(parsed_val.max(1));
+                        }
+                    }
+                }
+                Long("body_timeout") => {
+                    if let Ok(val) = parser.value() {
+                        if let Ok(parsed_val) = val.parse::<u64>() {
+                            config.body_timeout = Duration::from_secs(parsed_val.max(1));
+                        }
+                    }
+                }
+                Long("write_timeout") => {
+                    if let Ok(val) = parser.value() {
+                        if let Ok(parsed_val) = val.parse::<u64>() {
+                            config.write_timeout = Duration::from_secs(parsed_val.max(1));
+                        }
+                    }
+                }
+                Long("min_data_rate") => {
+                    if let Ok(val) = parser.value() {
+                        if let Ok(parsed_val) = val.parse() {
+                            config.min_data_rate = parsed_val;
+                        }
+                    }
+                }
+                Short('m') | Long("max_requests") => {
+                    if let Ok(val) = parser.value() {
+                        if let Ok(parsed_val) = val.parse::<usize>() {
+                            // A connection has to be able to serve at least one request
+                            config.max_requests = parsed_val.max(1);
+                        }
+                    }
+                }
+                Long("max_decoded_body") => {
+                    if let Ok(val) = parser.value() {
+                        if let Ok(parsed_val) = val.parse() {
+                            config.max_decoded_body = parsed_val;
+                        }
+                    }
+                }
+                Long("max_request_line") => {
+                    if let Ok(val) = parser.value() {
+                        if let Ok(parsed_val) = val.parse() {
+                            config.max_request_line = parsed_val;
+                        }
+                    }
+                }
+                Long("max_header_size") => {
+                    if let Ok(val) = parser.value() {
+                        if let Ok(parsed_val) = val.parse() {
+                            config.max_header_size = parsed_val;
+                        }
+                    }
+                }
+                Long("max_headers") => {
+                    if let Ok(val) = parser.value() {
+                        if let Ok(parsed_val) = val.parse() {
+                            config.max_headers = parsed_val;
+                        }
+                    }
+                }
+                Long("max_body") => {
+                    if let Ok(val) = parser.value() {
+                        if let Ok(parsed_val) = val.parse() {
+                            config.max_body = parsed_val;
+                        }
+                    }
+                }
+                Long("strict") => config.strict = true,
+                Long("no_date") => config.send_date = false,
+                Long("no_server") => config.send_server = false,
                 Short('h') | Long("help") => {
-                    println!("Usage: cargo run -- [-t | --target_dir=TARGET_DIR] [-a | --address=ADDRESS]");
+                    println!("Usage: cargo run -- [-t | --target_dir=TARGET_DIR] [-a | --address=ADDRESS] [-k | --keep_alive_timeout=SECONDS] [--header_timeout=SECONDS] [--body_timeout=SECONDS] [--write_timeout=SECONDS] [--min_data_rate=BYTES] [-m | --max_requests=COUNT] [--max_request_line=BYTES] [--max_header_size=BYTES] [--max_headers=COUNT] [--max_body=BYTES] [--max_decoded_body=BYTES] [--strict] [--no_date] [--no_server]");
                     std::process::exit(0);
                 }
                 _ => {
@@ -52,6 +154,20 @@ impl Default for Config {
         Config {
             address: ADDRESS.to_owned(),
             directory: Dir::default(),
+            keep_alive_timeout: Duration::from_secs(KEEP_ALIVE_TIMEOUT_SECS),
+            header_timeout: Duration::from_secs(HEADER_TIMEOUT_SECS),
+            body_timeout: Duration::from_secs(BODY_TIMEOUT_SECS),
+            write_timeout: Duration::from_secs(WRITE_TIMEOUT_SECS),
+            min_data_rate: MIN_DATA_RATE,
+            max_requests: MAX_KEEP_ALIVE_REQUESTS,
+            send_date: true,
+            send_server: true,
+            max_decoded_body: MAX_DECODED_BODY_SIZE,
+            max_request_line: MAX_REQUEST_LINE_LEN,
+            max_header_size: MAX_HEADER_SIZE,
+            max_headers: MAX_HEADER_COUNT,
+            max_body: MAX_BODY_SIZE,
+            strict: false,
         }
     }
 }
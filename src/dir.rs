@@ -1,12 +1,28 @@
-use crate::{constants::TARGET_DIR, Result};
+use crate::{constants::TARGET_DIR, errors::ClientError, Result};
 use std::{
-    fs::{create_dir, read, write},
+    fs::{create_dir, remove_file, write, File},
+    io::{Read, Seek, SeekFrom},
+    ops::Range,
     path::PathBuf,
+    time::SystemTime,
 };
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileMetadata {
+    pub len: u64,
+    pub modified: SystemTime,
+}
+
 pub trait FileSystemAccess {
-    fn try_read(&self, src: &str) -> Result<Vec<u8>>;
+    // A reader over the file, so it can be streamed instead of read into
+    // memory. Its length is in try_metadata.
+    fn try_open(&self, src: &str) -> Result<Box<dyn Read>>;
+    fn try_metadata(&self, src: &str) -> Result<FileMetadata>;
+    // Only the bytes in the range, without reading the rest of the file
+    fn try_read_range(&self, src: &str, range: &Range<u64>) -> Result<Box<dyn Read>>;
     fn try_write(&self, src: &str, d: &[u8]) -> Result<()>;
+    fn try_delete(&self, src: &str) -> Result<()>;
+    fn check_file_exists(&self, src: &str) -> bool;
     fn check_dir_exists(&self) -> bool;
     fn try_create(&self) -> Result<()>;
 }
@@ -34,14 +50,36 @@ impl FileSystemAccess for Dir {
     fn check_dir_exists(&self) -> bool {
         self.path.exists() && self.path.is_dir()
     }
-    fn try_read(&self, src: &str) -> Result<Vec<u8>> {
-        let d = read(self.path.join(src))?;
-        Ok(d)
+    fn try_open(&self, src: &str) -> Result<Box<dyn Read>> {
+        Ok(Box::new(File::open(self.path.join(src))?))
+    }
+    fn try_metadata(&self, src: &str) -> Result<FileMetadata> {
+        let metadata = self.path.join(src).metadata()?;
+        // A directory has metadata too, but nothing we could send
+        if !metadata.is_file() {
+            return Err(ClientError::NotFound.into());
+        }
+        Ok(FileMetadata {
+            len: metadata.len(),
+            modified: metadata.modified()?,
+        })
+    }
+    fn try_read_range(&self, src: &str, range: &Range<u64>) -> Result<Box<dyn Read>> {
+        let mut f = File::open(self.path.join(src))?;
+        f.seek(SeekFrom::Start(range.start))?;
+        Ok(Box::new(f.take(range.end - range.start)))
     }
     fn try_write(&self, src: &str, d: &[u8]) -> Result<()> {
         write(self.path.join(src), d)?;
         Ok(())
     }
+    fn try_delete(&self, src: &str) -> Result<()> {
+        remove_file(self.path.join(src))?;
+        Ok(())
+    }
+    fn check_file_exists(&self, src: &str) -> bool {
+        self.path.join(src).is_file()
+    }
     fn try_create(&self) -> Result<()> {
         if !self.check_dir_exists() {
             create_dir(&self.path)?;
@@ -49,3 +87,27 @@ impl FileSystemAccess for Dir {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    mod dir {
+        use crate::dir::{Dir, FileSystemAccess};
+        use crate::errors::{AppError, ClientError};
+        use std::fs::{create_dir_all, remove_dir_all, write};
+
+        #[test]
+        fn only_has_metadata_for_files() {
+            let path = std::env::temp_dir().join(format!("dir-test-{}", std::process::id()));
+            create_dir_all(path.join("sub")).unwrap();
+            write(path.join("file"), b"Hi!").unwrap();
+            let dir = Dir::new(path.to_str().unwrap());
+            assert_eq!(3, dir.try_metadata("file").unwrap().len);
+            assert_eq!(
+                AppError::Client(ClientError::NotFound),
+                dir.try_metadata("sub").unwrap_err()
+            );
+            remove_dir_all(&path).unwrap();
+        }
+    }
+}
@@ -1,43 +1,75 @@
 use std::error::Error;
 use std::fmt::{Debug, Display, Error as FmtErr, Formatter};
-use std::io::Error as IOError;
+use std::io::{Error as IOError, ErrorKind};
 use std::num::ParseIntError;
 use std::sync::{mpsc, PoisonError};
 
+use crate::http::StatusCode;
+
 #[derive(Debug, PartialEq)]
 pub enum ServerError {
     Internal,
     NotImplemented,
+    HttpVersionNotSupported,
 }
 
 impl Error for ServerError {}
 
-impl Display for ServerError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::result::Result<(), FmtErr> {
+impl ServerError {
+    pub fn status_code(&self) -> StatusCode {
         match self {
-            Self::Internal => write!(f, "500 Internal Server Error"),
-            Self::NotImplemented => write!(f, "501 Not Implemented"),
+            Self::Internal => StatusCode::InternalServerError,
+            Self::NotImplemented => StatusCode::NotImplemented,
+            Self::HttpVersionNotSupported => StatusCode::HttpVersionNotSupported,
         }
     }
 }
 
+impl Display for ServerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::result::Result<(), FmtErr> {
+        write!(f, "{}", self.status_code())
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum ClientError {
     NotFound,
     BadRequest,
+    NotAcceptable,
+    RequestTimeout,
+    PreconditionFailed,
+    ContentTooLarge,
+    UriTooLong,
+    UnsupportedMediaType,
+    ExpectationFailed,
+    RequestHeaderFieldsTooLarge,
 }
 
 impl Error for ClientError {}
 
-impl Display for ClientError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::result::Result<(), FmtErr> {
+impl ClientError {
+    pub fn status_code(&self) -> StatusCode {
         match self {
-            Self::NotFound => write!(f, "404 Not Found"),
-            Self::BadRequest => write!(f, "400 Bad Request"),
+            Self::NotFound => StatusCode::NotFound,
+            Self::BadRequest => StatusCode::BadRequest,
+            Self::NotAcceptable => StatusCode::NotAcceptable,
+            Self::RequestTimeout => StatusCode::RequestTimeout,
+            Self::PreconditionFailed => StatusCode::PreconditionFailed,
+            Self::ContentTooLarge => StatusCode::ContentTooLarge,
+            Self::UriTooLong => StatusCode::UriTooLong,
+            Self::UnsupportedMediaType => StatusCode::UnsupportedMediaType,
+            Self::ExpectationFailed => StatusCode::ExpectationFailed,
+            Self::RequestHeaderFieldsTooLarge => StatusCode::RequestHeaderFieldsTooLarge,
         }
     }
 }
 
+impl Display for ClientError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::result::Result<(), FmtErr> {
+        write!(f, "{}", self.status_code())
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum AppError {
     Client(ClientError),
@@ -46,6 +78,16 @@ pub enum AppError {
 
 impl Error for AppError {}
 
+impl AppError {
+    // What the client is told
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Client(e) => e.status_code(),
+            Self::Server(e) => e.status_code(),
+        }
+    }
+}
+
 impl Display for AppError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::result::Result<(), FmtErr> {
         write!(f, "{self:?}")
@@ -65,8 +107,14 @@ impl From<ServerError> for AppError {
 }
 
 impl From<IOError> for AppError {
-    fn from(_error: IOError) -> Self {
-        Self::Server(ServerError::Internal)
+    fn from(error: IOError) -> Self {
+        // Only the connection times out, and then it's the client being slow
+        match error.kind() {
+            ErrorKind::TimedOut | ErrorKind::WouldBlock => {
+                Self::Client(ClientError::RequestTimeout)
+            }
+            _ => Self::Server(ServerError::Internal),
+        }
     }
 }
 
@@ -3,69 +3,71 @@
 use crate::{
     dir::FileSystemAccess,
     errors::AppError,
-    http::{ClientError, Headers, Method, MimeType, Request, Response, ServerError, StatusCode},
+    http::{
+        conditional::Validators,
+        encoding::Encoding,
+        range::{self, ContentRange},
+        ClientError, Headers, Method, MimeType, Request, Response, ServerError, StatusCode,
+    },
     Result,
 };
-use std::io::Write;
+use std::{
+    io::{empty, Cursor, Read},
+    ops::Range,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
+// Handlers build the response, the router owns the stream and writes it out,
+// so that connection-level headers are applied in one place
 #[derive(Debug)]
-pub struct HandlerArg<'a, T>
-where
-    T: Write,
-{
+pub struct HandlerArg<'a> {
     pub req: &'a Request,
-    pub stream: &'a mut T,
 }
 
-impl<'a, T> HandlerArg<'a, T>
-where
-    T: Write,
-{
-    pub fn new(req: &'a Request, stream: &'a mut T) -> HandlerArg<'a, T> {
-        HandlerArg { req, stream }
+impl<'a> HandlerArg<'a> {
+    pub fn new(req: &'a Request) -> HandlerArg<'a> {
+        HandlerArg { req }
     }
 }
 
 #[derive(Debug)]
-pub struct FileHandlerArg<'a, T, U>
+pub struct FileHandlerArg<'a, U>
 where
-    T: Write,
     U: FileSystemAccess,
 {
     pub req: &'a Request,
-    pub stream: &'a mut T,
     pub target_dir: &'a U,
 }
 
-impl<'a, T, U> FileHandlerArg<'a, T, U>
+impl<'a, U> FileHandlerArg<'a, U>
 where
-    T: Write,
     U: FileSystemAccess,
 {
-    pub fn new(req: &'a Request, stream: &'a mut T, target_dir: &'a U) -> FileHandlerArg<'a, T, U> {
-        FileHandlerArg {
-            req,
-            stream,
-            target_dir,
-        }
+    pub fn new(req: &'a Request, target_dir: &'a U) -> FileHandlerArg<'a, U> {
+        FileHandlerArg { req, target_dir }
     }
 }
 
+// For the answers that list the methods a route allows
 #[derive(Debug)]
-pub struct ErrorHandlerArg<'a, T>
-where
-    T: Write,
-{
-    pub stream: &'a mut T,
+pub struct AllowHandlerArg<'a> {
+    pub allow: &'a [Method],
+}
+
+impl<'a> AllowHandlerArg<'a> {
+    pub fn new(allow: &'a [Method]) -> AllowHandlerArg<'a> {
+        AllowHandlerArg { allow }
+    }
+}
+
+#[derive(Debug)]
+pub struct ErrorHandlerArg {
     pub err: AppError,
 }
 
-impl<'a, T> ErrorHandlerArg<'a, T>
-where
-    T: Write,
-{
-    pub fn new(stream: &'a mut T, err: AppError) -> ErrorHandlerArg<'a, T> {
-        ErrorHandlerArg { stream, err }
+impl ErrorHandlerArg {
+    pub fn new(err: AppError) -> ErrorHandlerArg {
+        ErrorHandlerArg { err }
     }
 }
 
@@ -74,130 +76,302 @@ pub struct EmptyHandler;
 pub struct FileHandler;
 pub struct UserAgentHandler;
 pub struct NotFoundHandler;
+pub struct OptionsHandler;
+pub struct MethodNotAllowedHandler;
 pub struct ErrorHandler;
 
 pub trait Handler {
-    fn handle<T>(r: HandlerArg<T>) -> Result<()>
-    where
-        T: Write;
+    fn handle(r: HandlerArg) -> Result<Response>;
 }
 
 impl Handler for EchoHandler {
-    fn handle<T>(r: HandlerArg<T>) -> Result<()>
-    where
-        T: Write,
-    {
-        let body = r.req.body.as_slice();
-        let resp = Response::builder()
-            .body(Some(body.to_owned()))
-            .encoding(r.req.get_header(Headers::AcceptEncoding))
+    fn handle(r: HandlerArg) -> Result<Response> {
+        let body = r.req.param("msg").unwrap_or_default();
+        Response::builder()
+            .body(Some(body.as_bytes().to_owned()))
+            .encoding(r.req.get_header(Headers::AcceptEncoding).as_deref())
             .mime_type(MimeType::PlainText)
-            .build()?;
-        r.stream.write_all(&resp.as_bytes())?;
-        Ok(())
+            .build()
     }
 }
 
 impl Handler for EmptyHandler {
-    fn handle<T>(r: HandlerArg<T>) -> Result<()>
-    where
-        T: Write,
-    {
-        let resp = Response::ok()?;
-        r.stream.write_all(&resp.as_bytes())?;
-        Ok(())
+    fn handle(_r: HandlerArg) -> Result<Response> {
+        Response::ok()
     }
 }
 
 impl Handler for UserAgentHandler {
-    fn handle<T>(r: HandlerArg<T>) -> Result<()>
-    where
-        T: Write,
-    {
+    fn handle(r: HandlerArg) -> Result<Response> {
         let b = r
             .req
-            .get_header(Headers::UserAgent)
+            .headers
+            .get(Headers::UserAgent)
             .map(|b| b.as_bytes().to_owned());
-        let resp = Response::builder()
+        Response::builder()
             .body(b)
-            .encoding(r.req.get_header(Headers::ContentEncoding))
+            .encoding(r.req.get_header(Headers::AcceptEncoding).as_deref())
             .mime_type(MimeType::PlainText)
-            .build()?;
-        r.stream.write_all(&resp.as_bytes())?;
-        Ok(())
+            .build()
     }
 }
 
 impl Handler for NotFoundHandler {
-    fn handle<T>(r: HandlerArg<T>) -> Result<()>
-    where
-        T: Write,
-    {
-        let resp = Response::not_found()?;
-        r.stream.write_all(&resp.as_bytes())?;
-        Ok(())
+    fn handle(_r: HandlerArg) -> Result<Response> {
+        Response::not_found()
+    }
+}
+
+impl OptionsHandler {
+    pub fn handle(a: AllowHandlerArg) -> Result<Response> {
+        Response::builder()
+            .status_code(StatusCode::NoContent)
+            .allow(a.allow)
+            .build()
+    }
+}
+
+impl MethodNotAllowedHandler {
+    pub fn handle(a: AllowHandlerArg) -> Result<Response> {
+        Response::builder()
+            .status_code(StatusCode::MethodNotAllowed)
+            .allow(a.allow)
+            .build()
     }
 }
 
 impl FileHandler {
-    pub fn handle<T, U>(r: FileHandlerArg<T, U>) -> Result<()>
+    fn read<U>(r: &FileHandlerArg<U>, src: &str) -> Result<Response>
     where
-        T: Write,
         U: FileSystemAccess,
     {
-        let src = &r.req.path_parts[1];
-        match r.req.method {
-            Method::Get => {
-                if let Ok(body) = r.target_dir.try_read(src) {
-                    let resp = Response::builder()
-                        .status_code(StatusCode::Ok)
-                        .body(Some(body))
-                        .encoding(r.req.get_header(Headers::AcceptEncoding))
-                        .mime_type(MimeType::OctetStream)
-                        .build()?;
-                    r.stream.write_all(&resp.as_bytes())?;
-                } else {
-                    return Err(ClientError::NotFound.into());
-                }
+        let Ok(metadata) = r.target_dir.try_metadata(src) else {
+            return Err(ClientError::NotFound.into());
+        };
+        let validators = Validators::new(metadata.len, metadata.modified);
+        let len = metadata.len;
+        let mime_type = MimeType::from_file_name(src);
+
+        // https://www.rfc-editor.org/rfc/rfc9110#section-13.2.2 - If-None-Match
+        // wins, If-Modified-Since is only looked at without it
+        let not_modified = match (
+            r.req.get_header(Headers::IfNoneMatch).as_deref(),
+            r.req.get_header(Headers::IfModifiedSince).as_deref(),
+        ) {
+            (Some(etags), _) => !validators.none_match(etags),
+            (None, Some(since)) => !validators.modified_since(since),
+            (None, None) => false,
+        };
+        // https://www.rfc-editor.org/rfc/rfc9110#section-15.4.5 - with the
+        // same ETag and Vary as the 200 it saves sending, so the encoding is
+        // negotiated as if the file were going out. Nothing is read from it.
+        if not_modified {
+            return Response::builder()
+                .status_code(StatusCode::NotModified)
+                .stream(Box::new(empty()), Some(len))
+                .encoding(r.req.get_header(Headers::AcceptEncoding).as_deref())
+                .mime_type(mime_type)
+                .validators(validators)
+                .build();
+        }
+
+        // Range only applies to GET, and with If-Range only if the client
+        // still has the file as it is now, otherwise it gets all of it
+        let ranges = match (
+            r.req.method,
+            r.req.get_header(Headers::Range).as_deref(),
+            r.req.get_header(Headers::IfRange).as_deref(),
+        ) {
+            (Method::Get, Some(ranges), if_range)
+                if if_range.map_or(true, |i| validators.range_matches(i)) =>
+            {
+                range::parse(ranges)
             }
-            Method::Post => {
-                r.target_dir.try_write(src, &r.req.body)?;
-                // TODO: it's only created if it's created, right?
-                let resp = Response::created()?;
-                r.stream.write_all(&resp.as_bytes())?;
+            _ => None,
+        };
+        // Ranges are over the file as stored, so partial responses are never
+        // compressed
+        match ranges.map(|ranges| range::resolve(&ranges, len)) {
+            None => Response::builder()
+                .status_code(StatusCode::Ok)
+                .stream(r.target_dir.try_open(src)?, Some(len))
+                .encoding(r.req.get_header(Headers::AcceptEncoding).as_deref())
+                .mime_type(mime_type)
+                .accept_ranges()
+                .validators(validators)
+                .build(),
+            Some(ranges) if ranges.is_empty() => Response::builder()
+                .status_code(StatusCode::RangeNotSatisfiable)
+                .content_range(ContentRange::Unsatisfied(len))
+                .accept_ranges()
+                .build(),
+            Some(mut ranges) if ranges.len() == 1 => {
+                let range = ranges.remove(0);
+                Response::builder()
+                    .status_code(StatusCode::PartialContent)
+                    .stream(
+                        r.target_dir.try_read_range(src, &range)?,
+                        Some(range.end - range.start),
+                    )
+                    .mime_type(mime_type)
+                    .content_range(ContentRange::Satisfied(range, len))
+                    .accept_ranges()
+                    .validators(validators)
+                    .build()
+            }
+            Some(ranges) => {
+                let boundary = multipart_boundary();
+                let (body, body_len) =
+                    multipart_byteranges(r.target_dir, src, ranges, len, &mime_type, &boundary)?;
+                Response::builder()
+                    .status_code(StatusCode::PartialContent)
+                    .stream(body, Some(body_len))
+                    .mime_type(MimeType::MultipartByteRanges(boundary))
+                    .accept_ranges()
+                    .validators(validators)
+                    .build()
             }
-            _ => return Err(ServerError::Internal.into()),
         }
-        Ok(())
     }
-}
 
-impl ErrorHandler {
-    pub fn handle<T>(a: ErrorHandlerArg<T>) -> Result<()>
+    // Lets clients avoid overwriting each other's changes: If-Match only
+    // writes over the file they last saw, If-None-Match: * only creates
+    fn check_preconditions<U>(r: &FileHandlerArg<U>, src: &str) -> Result<()>
     where
-        T: Write,
+        U: FileSystemAccess,
     {
-        match a.err {
-            AppError::Client(ClientError::BadRequest) => {
-                let resp = Response::client_error()?;
-                a.stream.write_all(&resp.as_bytes())?;
+        let validators = r
+            .target_dir
+            .try_metadata(src)
+            .ok()
+            .map(|metadata| Validators::new(metadata.len, metadata.modified));
+        let if_match = r.req.get_header(Headers::IfMatch);
+        let if_none_match = r.req.get_header(Headers::IfNoneMatch);
+        let failed = match &validators {
+            Some(v) => {
+                if_match.is_some_and(|etags| !v.matches(&etags))
+                    || if_none_match.is_some_and(|etags| !v.none_match(&etags))
             }
-            AppError::Client(ClientError::NotFound) => {
-                let resp = Response::not_found()?;
-                a.stream.write_all(&resp.as_bytes())?;
+            None => if_match.is_some(),
+        };
+        if failed {
+            Err(ClientError::PreconditionFailed.into())
+        } else {
+            Ok(())
+        }
+    }
+
+    // https://www.rfc-editor.org/rfc/rfc9110#section-15.3.2 - points at the
+    // file that was just made
+    fn created<U>(r: &FileHandlerArg<U>) -> Result<Response>
+    where
+        U: FileSystemAccess,
+    {
+        Response::builder()
+            .status_code(StatusCode::Created)
+            .header(Headers::Location, r.req.raw_path.as_str())
+            .build()
+    }
+
+    pub fn handle<U>(r: FileHandlerArg<U>) -> Result<Response>
+    where
+        U: FileSystemAccess,
+    {
+        let Some(src) = r.req.param("name") else {
+            return Err(ClientError::NotFound.into());
+        };
+        if matches!(r.req.method, Method::Post | Method::Put | Method::Delete) {
+            Self::check_preconditions(&r, src)?;
+        }
+        match r.req.method {
+            Method::Get | Method::Head => Self::read(&r, src),
+            Method::Post => {
+                r.target_dir.try_write(src, &r.req.body)?;
+                // TODO: it's only created if it's created, right?
+                Self::created(&r)
             }
-            AppError::Server(ServerError::NotImplemented) => {
-                let resp = Response::builder()
-                    .status_code(crate::http::StatusCode::NotImplemented)
-                    .build()?;
-                a.stream.write_all(&resp.as_bytes())?;
+            Method::Put => {
+                let existed = r.target_dir.check_file_exists(src);
+                r.target_dir.try_write(src, &r.req.body)?;
+                if existed {
+                    Response::no_content()
+                } else {
+                    Self::created(&r)
+                }
             }
-            _ => {
-                let resp = Response::server_error()?;
-                a.stream.write_all(&resp.as_bytes())?;
+            Method::Delete => {
+                if !r.target_dir.check_file_exists(src) {
+                    return Err(ClientError::NotFound.into());
+                }
+                r.target_dir.try_delete(src)?;
+                Response::no_content()
             }
+            _ => Err(ServerError::Internal.into()),
         }
-        Ok(())
+    }
+}
+
+// Only has to be unlikely to turn up in the file itself
+fn multipart_boundary() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    format!("{nanos:032x}")
+}
+
+// https://www.rfc-editor.org/rfc/rfc9110#section-14.6 - each range as its own
+// part, read straight from the file as the body is sent. The length is known
+// up front, so this still goes out with a Content-Length.
+fn multipart_byteranges<U>(
+    target_dir: &U,
+    src: &str,
+    ranges: Vec<Range<u64>>,
+    len: u64,
+    mime_type: &MimeType,
+    boundary: &str,
+) -> Result<(Box<dyn Read>, u64)>
+where
+    U: FileSystemAccess,
+{
+    let mut body: Box<dyn Read> = Box::new(empty());
+    let mut body_len = 0;
+    for range in ranges {
+        let part_head = format!(
+            "--{boundary}\r\n{}: {}\r\n{}: {}\r\n\r\n",
+            Headers::ContentType,
+            mime_type,
+            Headers::ContentRange,
+            ContentRange::Satisfied(range.clone(), len)
+        );
+        body_len += part_head.len() as u64 + (range.end - range.start) + 2;
+        let part = Cursor::new(part_head)
+            .chain(target_dir.try_read_range(src, &range)?)
+            .chain(&b"\r\n"[..]);
+        body = Box::new(body.chain(part));
+    }
+    let end = format!("--{boundary}--\r\n");
+    body_len += end.len() as u64;
+    Ok((Box::new(body.chain(Cursor::new(end))), body_len))
+}
+
+impl ErrorHandler {
+    pub fn handle(a: ErrorHandlerArg) -> Result<Response> {
+        let builder = Response::builder().status_code(a.err.status_code());
+        match a.err {
+            // https://www.rfc-editor.org/rfc/rfc9110#section-12.5.3 - tell the
+            // client which codings it could have used
+            AppError::Client(ClientError::UnsupportedMediaType) => builder.header(
+                Headers::AcceptEncoding,
+                Encoding::SUPPORTED
+                    .iter()
+                    .map(Encoding::as_str)
+                    .collect::<Vec<&str>>()
+                    .join(", "),
+            ),
+            _ => builder,
+        }
+        .build()
     }
 }
 
@@ -205,21 +379,53 @@ impl ErrorHandler {
 mod tests {
 
     mod handlers {
-        use std::collections::HashMap;
+        use crate::dir::{FileMetadata, FileSystemAccess};
+        use crate::{
+            handlers::*,
+            http::{Request, Version},
+        };
+        use httpdate::fmt_http_date;
+        use std::time::Duration;
 
-        use crate::dir::FileSystemAccess;
-        use crate::{handlers::*, http::Request, router::Route};
+        // Far enough from the epoch that we can go back in time from it
+        fn mtime() -> SystemTime {
+            UNIX_EPOCH + Duration::from_secs(86_400)
+        }
 
-        struct MockDir;
+        struct MockDir {
+            exists: bool,
+        }
 
         impl FileSystemAccess for MockDir {
-            fn try_read(&self, src: &str) -> Result<Vec<u8>> {
-                let _ = src;
-                Ok(b"Hi!".to_vec())
+            fn try_open(&self, _src: &str) -> Result<Box<dyn std::io::Read>> {
+                Ok(Box::new(b"Hi!".as_slice()))
+            }
+            fn try_metadata(&self, _src: &str) -> Result<FileMetadata> {
+                if !self.exists {
+                    return Err(ClientError::NotFound.into());
+                }
+                Ok(FileMetadata {
+                    len: 3,
+                    modified: mtime(),
+                })
+            }
+            fn try_read_range(
+                &self,
+                _src: &str,
+                range: &std::ops::Range<u64>,
+            ) -> Result<Box<dyn std::io::Read>> {
+                let range = range.start as usize..range.end as usize;
+                Ok(Box::new(&b"Hi!"[range]))
             }
             fn try_write(&self, _src: &str, _d: &[u8]) -> Result<()> {
                 Ok(())
             }
+            fn try_delete(&self, _src: &str) -> Result<()> {
+                Ok(())
+            }
+            fn check_file_exists(&self, _src: &str) -> bool {
+                self.exists
+            }
             fn try_create(&self) -> Result<()> {
                 Ok(())
             }
@@ -228,66 +434,76 @@ mod tests {
             }
         }
 
+        // A request the way the router hands it over, with the parameters
+        // its route would take from the path
+        fn request(
+            method: Method,
+            path: &str,
+            headers: &[(Headers, &str)],
+            body: &[u8],
+        ) -> Request {
+            let path_parts: Vec<String> = path
+                .split('/')
+                .filter(|s| !s.is_empty())
+                .map(str::to_owned)
+                .collect();
+            let params = match path_parts.as_slice() {
+                [route, msg] if route == "echo" => vec![("msg".to_owned(), msg.to_owned())],
+                [route, name] if route == "files" => vec![("name".to_owned(), name.to_owned())],
+                _ => Vec::new(),
+            };
+            Request {
+                method,
+                version: Version::Http11,
+                headers: headers.iter().copied().collect(),
+                body: body.to_vec(),
+                path: path.to_owned(),
+                raw_path: path.to_owned(),
+                raw_query: None,
+                query: Vec::new(),
+                params,
+                path_parts,
+            }
+        }
+
         #[test]
         fn handles_echo() {
-            // TODO: some fixtures?
-            let req = Request {
-                method: Method::Get,
-                route: Route::Echo,
-                headers: HashMap::new(),
-                body: b"hello".to_vec(),
-                path: "/echo/hello".to_owned(),
-                path_parts: vec!["echo".to_owned(), "hello".to_owned()],
-            };
-            let mut stream = Vec::new();
-            let arg = HandlerArg::new(&req, &mut stream);
-            let _ = EchoHandler::handle(arg);
+            let req = request(Method::Get, "/echo/hello", &[], &[]);
+            let arg = HandlerArg::new(&req);
+            let resp = EchoHandler::handle(arg).unwrap();
             let expected = Response::builder()
                 .status_code(StatusCode::Ok)
                 .body(Some(b"hello".to_vec()))
                 .mime_type(MimeType::PlainText)
                 .build()
                 .unwrap();
-            assert_eq!(&expected.as_bytes(), &stream);
+            assert_eq!(expected.as_bytes(), resp.as_bytes());
         }
 
         #[test]
         fn handles_user_agent() {
-            // TODO: some fixtures?
-            let req = Request {
-                method: Method::Get,
-                route: Route::UserAgent,
-                headers: HashMap::from([(Headers::UserAgent, "Test-UA".to_owned())]),
-                body: b"Test-UA".to_vec(),
-                path: "/user-agent".to_owned(),
-                path_parts: vec!["user-agent".to_owned()],
-            };
-            let mut stream = Vec::new();
-            let arg = HandlerArg::new(&req, &mut stream);
-            let _ = UserAgentHandler::handle(arg);
+            let req = request(
+                Method::Get,
+                "/user-agent",
+                &[(Headers::UserAgent, "Test-UA")],
+                &[],
+            );
+            let arg = HandlerArg::new(&req);
+            let resp = UserAgentHandler::handle(arg).unwrap();
             let expected = Response::builder()
                 .status_code(StatusCode::Ok)
                 .body(Some(b"Test-UA".to_vec()))
                 .mime_type(MimeType::PlainText)
                 .build()
                 .unwrap();
-            assert_eq!(&expected.as_bytes(), &stream);
+            assert_eq!(expected.as_bytes(), resp.as_bytes());
         }
 
         #[test]
         fn handles_empty() {
-            // TODO: some fixtures?
-            let req = Request {
-                method: Method::Get,
-                route: Route::Empty,
-                headers: HashMap::new(),
-                body: Vec::new(),
-                path: "/".to_owned(),
-                path_parts: vec!["/".to_owned()],
-            };
-            let mut stream = Vec::new();
-            let arg = HandlerArg::new(&req, &mut stream);
-            let _ = EmptyHandler::handle(arg);
+            let req = request(Method::Get, "/", &[], &[]);
+            let arg = HandlerArg::new(&req);
+            let resp = EmptyHandler::handle(arg).unwrap();
             let expected = Response::builder()
                 .status_code(StatusCode::Ok)
                 .body(None)
@@ -295,84 +511,323 @@ mod tests {
                 .mime_type(MimeType::PlainText)
                 .build()
                 .unwrap();
-            assert_eq!(&expected.as_bytes(), &stream);
+            assert_eq!(expected.as_bytes(), resp.as_bytes());
         }
 
         #[test]
         fn handles_read_file() {
-            let req = Request {
-                method: Method::Get,
-                route: Route::Files,
-                headers: HashMap::new(),
-                body: Vec::new(),
-                path: "/files/test".to_owned(),
-                path_parts: vec!["files".to_owned(), "test".to_owned()],
-            };
-            let mut stream = Vec::new();
-            let target_dir = MockDir {};
-            let arg = FileHandlerArg::new(&req, &mut stream, &target_dir);
-            let _ = FileHandler::handle(arg);
+            let req = request(Method::Get, "/files/test", &[], &[]);
+            let target_dir = MockDir { exists: true };
+            let arg = FileHandlerArg::new(&req, &target_dir);
+            let resp = FileHandler::handle(arg).unwrap();
             let expected = Response::builder()
                 .status_code(StatusCode::Ok)
                 .mime_type(MimeType::OctetStream)
                 .body(Some(b"Hi!".to_vec()))
+                .accept_ranges()
+                .validators(Validators::new(3, mtime()))
                 .build()
                 .unwrap();
-            assert_eq!(&expected.as_bytes(), &stream);
+            let mut stream = Vec::new();
+            resp.write_to(&mut stream).unwrap();
+            assert_eq!(expected.as_bytes(), stream);
         }
 
         #[test]
         fn handles_write_file() {
-            let req = Request {
-                method: Method::Post,
-                route: Route::Files,
-                headers: HashMap::new(),
-                body: b"Hi!".to_vec(),
-                path: "/files/test".to_owned(),
-                path_parts: vec!["files".to_owned(), "test".to_owned()],
-            };
-            let mut stream = Vec::new();
-            let target_dir = MockDir {};
-            let arg = FileHandlerArg::new(&req, &mut stream, &target_dir);
-            let _ = FileHandler::handle(arg);
+            let req = request(Method::Post, "/files/test", &[], b"Hi!");
+            let target_dir = MockDir { exists: true };
+            let arg = FileHandlerArg::new(&req, &target_dir);
+            let resp = FileHandler::handle(arg).unwrap();
             let expected = Response::builder()
                 .status_code(StatusCode::Created)
-                .mime_type(MimeType::PlainText)
+                .header(Headers::Location, "/files/test")
                 .build()
                 .unwrap();
-            assert_eq!(&expected.as_bytes(), &stream);
+            assert_eq!(expected.as_bytes(), resp.as_bytes());
+        }
+
+        fn written(resp: Response) -> String {
+            let mut stream = Vec::new();
+            resp.write_to(&mut stream).unwrap();
+            String::from_utf8(stream).unwrap()
+        }
+
+        #[test]
+        fn handles_single_range() {
+            let req = request(
+                Method::Get,
+                "/files/test",
+                &[(Headers::Range, "bytes=1-")],
+                &[],
+            );
+            let target_dir = MockDir { exists: true };
+            let arg = FileHandlerArg::new(&req, &target_dir);
+            let resp = written(FileHandler::handle(arg).unwrap());
+            assert!(resp.starts_with("HTTP/1.1 206 Partial Content\r\n"));
+            assert!(resp.contains("\r\nContent-Range: bytes 1-2/3\r\n"));
+            assert!(resp.contains("\r\nContent-Length: 2\r\n"));
+            assert!(resp.ends_with("\r\n\r\ni!"));
+        }
+
+        #[test]
+        fn handles_multiple_ranges() {
+            let req = request(
+                Method::Get,
+                "/files/test",
+                &[(Headers::Range, "bytes=0-0,-1")],
+                &[],
+            );
+            let target_dir = MockDir { exists: true };
+            let arg = FileHandlerArg::new(&req, &target_dir);
+            let resp = written(FileHandler::handle(arg).unwrap());
+            let (head, body) = resp.split_once("\r\n\r\n").unwrap();
+            let boundary = head
+                .split_once("multipart/byteranges; boundary=")
+                .unwrap()
+                .1
+                .lines()
+                .next()
+                .unwrap();
+            assert!(head.contains(&format!("Content-Length: {}", body.len())));
+            assert_eq!(
+                format!("--{boundary}\r\nContent-Type: application/octet-stream\r\nContent-Range: bytes 0-0/3\r\n\r\nH\r\n--{boundary}\r\nContent-Type: application/octet-stream\r\nContent-Range: bytes 2-2/3\r\n\r\n!\r\n--{boundary}--\r\n"),
+                body
+            );
+        }
+
+        #[test]
+        fn handles_unsatisfiable_range() {
+            let req = request(
+                Method::Get,
+                "/files/test",
+                &[(Headers::Range, "bytes=5-")],
+                &[],
+            );
+            let target_dir = MockDir { exists: true };
+            let arg = FileHandlerArg::new(&req, &target_dir);
+            let resp = written(FileHandler::handle(arg).unwrap());
+            assert!(resp.starts_with("HTTP/1.1 416 Range Not Satisfiable\r\n"));
+            assert!(resp.contains("\r\nContent-Range: bytes */3\r\n"));
+        }
+
+        #[test]
+        fn handles_not_modified() {
+            let etag = Validators::new(3, mtime()).etag;
+            for req in [
+                request(
+                    Method::Get,
+                    "/files/test",
+                    &[(Headers::IfNoneMatch, &etag)],
+                    &[],
+                ),
+                request(
+                    Method::Get,
+                    "/files/test",
+                    &[(Headers::IfModifiedSince, &fmt_http_date(mtime()))],
+                    &[],
+                ),
+            ] {
+                let target_dir = MockDir { exists: true };
+                let arg = FileHandlerArg::new(&req, &target_dir);
+                let resp = written(FileHandler::handle(arg).unwrap());
+                assert!(resp.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+                assert!(resp.contains(&format!("\r\nETag: {etag}\r\n")));
+                assert!(resp.contains("\r\nLast-Modified: Fri, 02 Jan 1970 00:00:00 GMT\r\n"));
+            }
+        }
+
+        #[test]
+        fn types_files_by_name() {
+            for (name, mime_type) in [
+                ("test", "application/octet-stream"),
+                ("notes.TXT", "text/plain"),
+                ("index.html", "text/html"),
+                ("backup.tar.gz", "application/gzip"),
+            ] {
+                let req = request(Method::Get, &format!("/files/{name}"), &[], &[]);
+                let target_dir = MockDir { exists: true };
+                let arg = FileHandlerArg::new(&req, &target_dir);
+                let resp = written(FileHandler::handle(arg).unwrap());
+                assert!(
+                    resp.contains(&format!("\r\nContent-Type: {mime_type}\r\n")),
+                    "{resp}"
+                );
+            }
+        }
+
+        #[test]
+        fn handles_modified() {
+            let modified = fmt_http_date(mtime() - Duration::from_secs(1));
+            let req = request(
+                Method::Get,
+                "/files/test",
+                &[
+                    (Headers::IfNoneMatch, "\"stale\""),
+                    // If-None-Match takes precedence
+                    (Headers::IfModifiedSince, "Fri, 02 Jan 1970 00:00:00 GMT"),
+                ],
+                &[],
+            );
+            let target_dir = MockDir { exists: true };
+            let arg = FileHandlerArg::new(&req, &target_dir);
+            assert!(written(FileHandler::handle(arg).unwrap()).starts_with("HTTP/1.1 200 OK\r\n"));
+            let req = request(
+                Method::Get,
+                "/files/test",
+                &[(Headers::IfModifiedSince, &modified)],
+                &[],
+            );
+            let arg = FileHandlerArg::new(&req, &target_dir);
+            assert!(written(FileHandler::handle(arg).unwrap()).starts_with("HTTP/1.1 200 OK\r\n"));
+        }
+
+        #[test]
+        fn handles_if_range() {
+            let etag = Validators::new(3, mtime()).etag;
+            let target_dir = MockDir { exists: true };
+            let req = request(
+                Method::Get,
+                "/files/test",
+                &[(Headers::Range, "bytes=1-"), (Headers::IfRange, &etag)],
+                &[],
+            );
+            let arg = FileHandlerArg::new(&req, &target_dir);
+            assert!(written(FileHandler::handle(arg).unwrap())
+                .starts_with("HTTP/1.1 206 Partial Content\r\n"));
+            let req = request(
+                Method::Get,
+                "/files/test",
+                &[
+                    (Headers::Range, "bytes=1-"),
+                    (Headers::IfRange, "\"stale\""),
+                ],
+                &[],
+            );
+            let arg = FileHandlerArg::new(&req, &target_dir);
+            assert!(written(FileHandler::handle(arg).unwrap()).starts_with("HTTP/1.1 200 OK\r\n"));
+        }
+
+        #[test]
+        fn handles_put_file() {
+            let req = request(Method::Put, "/files/test", &[], b"Hi!");
+            for (exists, expected) in [
+                (
+                    false,
+                    Response::builder()
+                        .status_code(StatusCode::Created)
+                        .header(Headers::Location, "/files/test"),
+                ),
+                (true, Response::builder().status_code(StatusCode::NoContent)),
+            ] {
+                let target_dir = MockDir { exists };
+                let arg = FileHandlerArg::new(&req, &target_dir);
+                let resp = FileHandler::handle(arg).unwrap();
+                let expected = expected.build().unwrap();
+                assert_eq!(expected.as_bytes(), resp.as_bytes());
+            }
+        }
+
+        #[test]
+        fn handles_write_preconditions() {
+            let etag = Validators::new(3, mtime()).etag;
+            for (method, exists, header, value, ok) in [
+                (Method::Put, true, Headers::IfMatch, etag.as_str(), true),
+                (Method::Put, true, Headers::IfMatch, "\"stale\"", false),
+                (Method::Post, false, Headers::IfMatch, "*", false),
+                (Method::Post, false, Headers::IfNoneMatch, "*", true),
+                (Method::Put, true, Headers::IfNoneMatch, "*", false),
+                (Method::Delete, true, Headers::IfMatch, "\"stale\"", false),
+            ] {
+                let mut req = request(method, "/files/test", &[], b"Hi!");
+                req.headers.append(header, value);
+                let target_dir = MockDir { exists };
+                let arg = FileHandlerArg::new(&req, &target_dir);
+                match FileHandler::handle(arg) {
+                    Ok(_) => assert!(ok),
+                    Err(e) => {
+                        assert!(!ok);
+                        assert_eq!(AppError::Client(ClientError::PreconditionFailed), e);
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn handles_delete_file() {
+            let req = request(Method::Delete, "/files/test", &[], b"Hi!");
+            let target_dir = MockDir { exists: true };
+            let arg = FileHandlerArg::new(&req, &target_dir);
+            let resp = FileHandler::handle(arg).unwrap();
+            assert_eq!(Response::no_content().unwrap().as_bytes(), resp.as_bytes());
+
+            let target_dir = MockDir { exists: false };
+            let arg = FileHandlerArg::new(&req, &target_dir);
+            assert_eq!(
+                AppError::Client(ClientError::NotFound),
+                FileHandler::handle(arg).unwrap_err()
+            );
+        }
+
+        #[test]
+        fn needs_a_file_name() {
+            let mut req = request(Method::Get, "/files/test", &[], b"Hi!");
+            req.params.clear();
+            let target_dir = MockDir { exists: true };
+            let arg = FileHandlerArg::new(&req, &target_dir);
+            assert_eq!(
+                AppError::Client(ClientError::NotFound),
+                FileHandler::handle(arg).unwrap_err()
+            );
+        }
+
+        #[test]
+        fn handles_not_acceptable() {
+            let req = request(
+                Method::Get,
+                "/echo/hello",
+                &[(Headers::AcceptEncoding, "identity;q=0")],
+                &[],
+            );
+            let err = EchoHandler::handle(HandlerArg::new(&req)).unwrap_err();
+            assert_eq!(AppError::Client(ClientError::NotAcceptable), err);
+            let resp = ErrorHandler::handle(ErrorHandlerArg { err }).unwrap();
+            assert!(resp
+                .as_bytes()
+                .starts_with(b"HTTP/1.1 406 Not Acceptable\r\n"));
         }
 
         #[test]
         fn handles_not_found_error() {
-            let mut stream = Vec::new();
             let arg = ErrorHandlerArg {
-                stream: &mut stream,
                 err: AppError::Client(ClientError::NotFound),
             };
-            let _ = ErrorHandler::handle(arg);
-            assert_eq!(stream, Response::not_found().unwrap().as_bytes());
+            let resp = ErrorHandler::handle(arg).unwrap();
+            assert_eq!(resp.as_bytes(), Response::not_found().unwrap().as_bytes());
         }
         #[test]
         fn handles_bad_request_error() {
-            let mut stream = Vec::new();
             let arg = ErrorHandlerArg {
-                stream: &mut stream,
                 err: AppError::Client(ClientError::BadRequest),
             };
-            let _ = ErrorHandler::handle(arg);
-            assert_eq!(stream, Response::client_error().unwrap().as_bytes());
+            let resp = ErrorHandler::handle(arg).unwrap();
+            assert_eq!(
+                resp.as_bytes(),
+                Response::builder()
+                    .status_code(StatusCode::BadRequest)
+                    .build()
+                    .unwrap()
+                    .as_bytes()
+            );
         }
         #[test]
         fn handles_not_implemented_error() {
-            let mut stream = Vec::new();
             let arg = ErrorHandlerArg {
-                stream: &mut stream,
                 err: AppError::Server(ServerError::NotImplemented),
             };
-            let _ = ErrorHandler::handle(arg);
+            let resp = ErrorHandler::handle(arg).unwrap();
             assert_eq!(
-                stream,
+                resp.as_bytes(),
                 Response::builder()
                     .status_code(StatusCode::NotImplemented)
                     .build()
@@ -382,13 +837,18 @@ mod tests {
         }
         #[test]
         fn handles_generic_server_error() {
-            let mut stream = Vec::new();
             let arg = ErrorHandlerArg {
-                stream: &mut stream,
                 err: AppError::Server(ServerError::Internal),
             };
-            let _ = ErrorHandler::handle(arg);
-            assert_eq!(stream, Response::server_error().unwrap().as_bytes());
+            let resp = ErrorHandler::handle(arg).unwrap();
+            assert_eq!(
+                resp.as_bytes(),
+                Response::builder()
+                    .status_code(StatusCode::InternalServerError)
+                    .build()
+                    .unwrap()
+                    .as_bytes()
+            );
         }
     }
 }
@@ -0,0 +1,146 @@
+use std::io::{BufRead, ErrorKind, Read, Write};
+
+use crate::{errors::ClientError, Result};
+
+// Reads one CRLF (or, unless strict, bare LF) terminated line, failing if
+// the stream ends before the line does. The line ending is left off.
+fn read_line<R: BufRead>(buf: &mut R, strict: bool) -> Result<String> {
+    let mut line = String::new();
+    if buf.read_line(&mut line)? == 0 || !line.ends_with('\n') {
+        return Err(ClientError::BadRequest.into());
+    }
+    line.pop();
+    if line.ends_with('\r') {
+        line.pop();
+    } else if strict {
+        return Err(ClientError::BadRequest.into());
+    }
+    Ok(line)
+}
+
+// chunk-size = 1*HEXDIG, which from_str_radix alone would let a sign into.
+// Whitespace before any extensions (BWS) is only allowed if we're not strict.
+fn chunk_size(line: &str, strict: bool) -> Result<u64> {
+    let size = line.split(';').next().unwrap_or_default();
+    let size = if strict { size } else { size.trim() };
+    if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(ClientError::BadRequest.into());
+    }
+    u64::from_str_radix(size, 16).map_err(|_| ClientError::BadRequest.into())
+}
+
+// https://www.rfc-editor.org/rfc/rfc9112#section-7.1
+// chunk = chunk-size [ chunk-ext ] CRLF chunk-data CRLF
+// We don't do anything with chunk extensions or trailer fields, but they have
+// to be read past so that the connection is left at the next request. No
+// more than `limit` bytes are read, the framing included, so that extensions
+// and trailers can't go on forever either.
+pub fn decode<R: BufRead>(buf: &mut R, limit: u64, strict: bool) -> Result<Vec<u8>> {
+    let mut buf = buf.take(limit);
+    decode_chunks(&mut buf, strict).map_err(|e| {
+        if buf.limit() == 0 {
+            ClientError::ContentTooLarge.into()
+        } else {
+            e
+        }
+    })
+}
+
+fn decode_chunks<R: BufRead>(buf: &mut R, strict: bool) -> Result<Vec<u8>> {
+    let mut body: Vec<u8> = vec![];
+    loop {
+        let size = chunk_size(&read_line(buf, strict)?, strict)?;
+        if size == 0 {
+            break;
+        }
+        let read = buf.take(size).read_to_end(&mut body)?;
+        // The data is followed by a line ending and nothing else
+        if read as u64 != size || !read_line(buf, strict)?.is_empty() {
+            return Err(ClientError::BadRequest.into());
+        }
+    }
+    // Trailer section, ended by an empty line
+    while !read_line(buf, strict)?.is_empty() {}
+    Ok(body)
+}
+
+// Copies everything from the reader to the writer, one chunk per read, and
+// finishes with the last (empty) chunk. We never send trailers.
+pub fn encode<R: Read, W: Write>(mut reader: R, writer: &mut W) -> Result<()> {
+    let mut chunk = [0; 8 * 1024];
+    loop {
+        let read = match reader.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        write!(writer, "{read:x}\r\n")?;
+        writer.write_all(&chunk[..read])?;
+        writer.write_all(b"\r\n")?;
+    }
+    writer.write_all(b"0\r\n\r\n")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod chunked {
+        use crate::errors::{AppError, ClientError};
+        use crate::http::chunked::decode;
+
+        #[test]
+        fn decodes_chunks() {
+            let mut body = b"4\r\nWiki\r\n5\r\npedia\r\n0\r\n\r\nGET".as_slice();
+            assert_eq!(
+                b"Wikipedia".to_vec(),
+                decode(&mut body, 1024, false).unwrap()
+            );
+            // The next request is left alone
+            assert_eq!(b"GET", body);
+        }
+
+        #[test]
+        fn skips_extensions_and_trailers() {
+            let mut body =
+                b"4;name=value\r\nWiki\r\n0\r\nExpires: never\r\nX-Trailer: yes\r\n\r\n".as_slice();
+            assert_eq!(b"Wiki".to_vec(), decode(&mut body, 1024, false).unwrap());
+            assert!(body.is_empty());
+        }
+
+        #[test]
+        fn rejects_malformed_chunks() {
+            for body in [
+                b"zz\r\nWiki\r\n0\r\n\r\n".as_slice(),
+                b"4\r\nWikipedia\r\n0\r\n\r\n".as_slice(),
+                b"4\r\nWi".as_slice(),
+                b"0\r\n".as_slice(),
+            ] {
+                let mut body = body;
+                assert_eq!(
+                    AppError::Client(ClientError::BadRequest),
+                    decode(&mut body, 1024, false).unwrap_err()
+                );
+            }
+        }
+
+        #[test]
+        fn limits_chunked_body() {
+            let body = b"4\r\nWiki\r\n5\r\npedia\r\n0\r\n\r\n";
+            assert!(decode(&mut body.as_slice(), body.len() as u64, false).is_ok());
+            for limit in [body.len() as u64 - 1, 8] {
+                assert_eq!(
+                    AppError::Client(ClientError::ContentTooLarge),
+                    decode(&mut body.as_slice(), limit, false).unwrap_err()
+                );
+            }
+            // Trailers count too
+            let mut body = b"0\r\nX-Trailer: aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".as_slice();
+            assert_eq!(
+                AppError::Client(ClientError::ContentTooLarge),
+                decode(&mut body, 32, false).unwrap_err()
+            );
+        }
+    }
+}
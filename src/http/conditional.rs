@@ -0,0 +1,125 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use httpdate::parse_http_date;
+
+// https://www.rfc-editor.org/rfc/rfc9110#section-13 - the validators we send
+// with a file and check conditional requests against
+#[derive(Debug, PartialEq, Clone)]
+pub struct Validators {
+    pub etag: String,
+    pub last_modified: SystemTime,
+}
+
+impl Validators {
+    // The ETag is made from the size and modification time, so it changes
+    // whenever the file is written without having to hash its contents
+    pub fn new(len: u64, modified: SystemTime) -> Self {
+        let modified = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+        Self {
+            etag: format!("\"{len:x}-{:x}\"", modified.as_nanos()),
+            // HTTP dates only go down to the second
+            last_modified: UNIX_EPOCH + std::time::Duration::from_secs(modified.as_secs()),
+        }
+    }
+
+    // For when what we send isn't byte-for-byte the file, e.g. compressed
+    pub fn into_weak(mut self) -> Self {
+        if !self.etag.starts_with("W/") {
+            self.etag = format!("W/{}", self.etag);
+        }
+        self
+    }
+
+    // If-Match uses strong comparison, "*" matches any current file
+    pub fn matches(&self, header: &str) -> bool {
+        etags(header).any(|etag| etag == "*" || etag == self.etag)
+    }
+
+    // If-None-Match uses weak comparison, "*" matches any current file
+    pub fn none_match(&self, header: &str) -> bool {
+        !etags(header).any(|etag| etag == "*" || weak(etag) == weak(&self.etag))
+    }
+
+    // A date we can't parse means the condition is ignored
+    pub fn modified_since(&self, header: &str) -> bool {
+        parse_http_date(header.trim()).map_or(true, |since| self.last_modified > since)
+    }
+
+    // If-Range needs a strong match, either of the ETag or the exact date
+    pub fn range_matches(&self, header: &str) -> bool {
+        let header = header.trim();
+        if header.starts_with('"') {
+            header == self.etag
+        } else {
+            parse_http_date(header).is_ok_and(|date| date == self.last_modified)
+        }
+    }
+}
+
+fn etags(header: &str) -> impl Iterator<Item = &str> {
+    header.split(',').map(str::trim).filter(|e| !e.is_empty())
+}
+
+fn weak(etag: &str) -> &str {
+    etag.strip_prefix("W/").unwrap_or(etag)
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod conditional {
+        use crate::http::conditional::Validators;
+        use httpdate::fmt_http_date;
+        use std::time::{Duration, UNIX_EPOCH};
+
+        fn validators() -> Validators {
+            Validators::new(3, UNIX_EPOCH + Duration::from_millis(1_700_000_000_500))
+        }
+
+        #[test]
+        fn builds_validators() {
+            let v = validators();
+            assert_eq!("\"3-17979cfe53f76500\"", v.etag);
+            assert_eq!(
+                "Tue, 14 Nov 2023 22:13:20 GMT",
+                fmt_http_date(v.last_modified)
+            );
+            assert_eq!("W/\"3-17979cfe53f76500\"", v.into_weak().into_weak().etag);
+        }
+
+        #[test]
+        fn checks_if_match() {
+            let v = validators();
+            assert!(v.matches("\"other\", \"3-17979cfe53f76500\""));
+            assert!(v.matches("*"));
+            assert!(!v.matches("W/\"3-17979cfe53f76500\""));
+            assert!(!v.matches("\"other\""));
+        }
+
+        #[test]
+        fn checks_if_none_match() {
+            let v = validators();
+            assert!(!v.none_match("\"3-17979cfe53f76500\""));
+            assert!(!v.none_match("\"other\", W/\"3-17979cfe53f76500\""));
+            assert!(!v.none_match("*"));
+            assert!(v.none_match("\"other\""));
+        }
+
+        #[test]
+        fn checks_if_modified_since() {
+            let v = validators();
+            assert!(!v.modified_since("Tue, 14 Nov 2023 22:13:20 GMT"));
+            assert!(v.modified_since("Tue, 14 Nov 2023 22:13:19 GMT"));
+            assert!(v.modified_since("yesterday"));
+        }
+
+        #[test]
+        fn checks_if_range() {
+            let v = validators();
+            assert!(v.range_matches("\"3-17979cfe53f76500\""));
+            assert!(!v.range_matches("W/\"3-17979cfe53f76500\""));
+            assert!(v.range_matches("Tue, 14 Nov 2023 22:13:20 GMT"));
+            assert!(!v.range_matches("Tue, 14 Nov 2023 22:13:21 GMT"));
+        }
+    }
+}
@@ -0,0 +1,297 @@
+use std::{fmt::Display, io::Read};
+
+use flate2::{
+    read::{GzEncoder, MultiGzDecoder},
+    Compression,
+};
+
+// https://www.rfc-editor.org/rfc/rfc9110#section-8.4.1 - the content codings
+// we can compress a body with. Gzip is always there, the rest are cargo
+// features.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Encoding {
+    Identity,
+    Gzip,
+    #[cfg(feature = "deflate")]
+    Deflate,
+    #[cfg(feature = "brotli")]
+    Brotli,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Encoding {
+    // Our order of preference when the client likes several equally
+    pub const SUPPORTED: &'static [Encoding] = &[
+        #[cfg(feature = "zstd")]
+        Self::Zstd,
+        #[cfg(feature = "brotli")]
+        Self::Brotli,
+        Self::Gzip,
+        #[cfg(feature = "deflate")]
+        Self::Deflate,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Identity => "identity",
+            Self::Gzip => "gzip",
+            #[cfg(feature = "deflate")]
+            Self::Deflate => "deflate",
+            #[cfg(feature = "brotli")]
+            Self::Brotli => "br",
+            #[cfg(feature = "zstd")]
+            Self::Zstd => "zstd",
+        }
+    }
+
+    // A coding named in Content-Encoding or Accept-Encoding
+    pub fn from_token(token: &str) -> Option<Self> {
+        let token = token.trim().to_ascii_lowercase();
+        // x-gzip is an old name for gzip
+        if token == "x-gzip" {
+            return Some(Self::Gzip);
+        }
+        Self::SUPPORTED
+            .iter()
+            .chain([&Self::Identity])
+            .find(|encoding| encoding.as_str() == token)
+            .copied()
+    }
+
+    // Compresses whatever is read through it
+    pub fn encoder(&self, reader: Box<dyn Read>) -> std::io::Result<Box<dyn Read>> {
+        Ok(match self {
+            Self::Identity => reader,
+            Self::Gzip => Box::new(GzEncoder::new(reader, Compression::default())),
+            // "deflate" in HTTP is the zlib format, not raw deflate
+            #[cfg(feature = "deflate")]
+            Self::Deflate => Box::new(flate2::read::ZlibEncoder::new(
+                reader,
+                Compression::default(),
+            )),
+            // The highest qualities are far too slow to do on every request
+            #[cfg(feature = "brotli")]
+            Self::Brotli => Box::new(brotli::CompressorReader::new(reader, 8 * 1024, 5, 22)),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Box::new(zstd::stream::read::Encoder::new(reader, 3)?),
+        })
+    }
+
+    // Decompresses whatever is read through it
+    pub fn decoder(&self, reader: Box<dyn Read>) -> std::io::Result<Box<dyn Read>> {
+        Ok(match self {
+            Self::Identity => reader,
+            // Gzip files can be several members one after the other, e.g.
+            // logs that were compressed and appended to
+            Self::Gzip => Box::new(MultiGzDecoder::new(reader)),
+            #[cfg(feature = "deflate")]
+            Self::Deflate => Box::new(flate2::read::ZlibDecoder::new(reader)),
+            #[cfg(feature = "brotli")]
+            Self::Brotli => Box::new(brotli::Decompressor::new(reader, 8 * 1024)),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Box::new(zstd::stream::read::Decoder::new(reader)?),
+        })
+    }
+}
+
+impl Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+// https://www.rfc-editor.org/rfc/rfc9110#section-12.5.3
+// Each coding the client listed, with its weight in thousandths (q=0.5 is
+// 500). No header at all is kept apart from an empty one: the first means
+// anything goes, the second that only identity does.
+#[derive(Debug, PartialEq)]
+pub struct AcceptEncoding {
+    codings: Option<Vec<(String, u16)>>,
+}
+
+impl AcceptEncoding {
+    pub fn parse(header: Option<&str>) -> Self {
+        let codings = header.map(|header| {
+            header
+                .split(',')
+                .filter_map(|element| {
+                    let mut params = element.split(';').map(str::trim);
+                    let coding = params.next()?.to_ascii_lowercase();
+                    if coding.is_empty() {
+                        return None;
+                    }
+                    // An element with a weight we can't read is dropped
+                    let q = match params.find_map(|p| {
+                        p.split_once('=')
+                            .filter(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+                    }) {
+                        Some((_, q)) => qvalue(q.trim())?,
+                        None => 1000,
+                    };
+                    // Named as we name it, if it's one we know
+                    let coding = Encoding::from_token(&coding)
+                        .map_or(coding, |encoding| encoding.as_str().to_owned());
+                    Some((coding, q))
+                })
+                .collect()
+        });
+        Self { codings }
+    }
+
+    fn quality(&self, encoding: Encoding) -> u16 {
+        let Some(codings) = &self.codings else {
+            return 1000;
+        };
+        let listed = |name: &str| codings.iter().find(|(c, _)| c == name).map(|(_, q)| *q);
+        listed(encoding.as_str())
+            .or_else(|| listed("*"))
+            // Identity is fine unless it is ruled out, but anything listed
+            // beats it
+            .unwrap_or(if encoding == Encoding::Identity { 1 } else { 0 })
+    }
+
+    // The coding to send a body with, or None if there isn't one the client
+    // will take (406). With `compress` off we'd rather not compress, so
+    // identity is chosen whenever it is acceptable.
+    pub fn choose(&self, compress: bool) -> Option<Encoding> {
+        // Without the header we don't know what the client can decode, so
+        // even though anything is acceptable we don't compress
+        if self.codings.is_none() {
+            return Some(Encoding::Identity);
+        }
+        if !compress && self.quality(Encoding::Identity) > 0 {
+            return Some(Encoding::Identity);
+        }
+        let mut best = None;
+        let mut best_q = 0;
+        for encoding in Encoding::SUPPORTED.iter().chain([&Encoding::Identity]) {
+            let q = self.quality(*encoding);
+            if q > best_q {
+                best = Some(*encoding);
+                best_q = q;
+            }
+        }
+        best
+    }
+}
+
+// https://www.rfc-editor.org/rfc/rfc9110#section-12.4.2
+// qvalue = ( "0" [ "." 0*3DIGIT ] ) / ( "1" [ "." 0*3("0") ] )
+fn qvalue(q: &str) -> Option<u16> {
+    let (int, frac) = q.split_once('.').unwrap_or((q, ""));
+    if frac.len() > 3 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let frac = format!("{frac:0<3}").parse::<u16>().ok()?;
+    match int {
+        "0" => Some(frac),
+        "1" if frac == 0 => Some(1000),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod encoding {
+        use crate::http::encoding::{AcceptEncoding, Encoding};
+
+        fn choose(header: Option<&str>) -> Option<Encoding> {
+            AcceptEncoding::parse(header).choose(true)
+        }
+
+        #[test]
+        fn names_encodings() {
+            assert_eq!(Some(Encoding::Gzip), Encoding::from_token(" X-GZIP"));
+            assert_eq!(Some(Encoding::Identity), Encoding::from_token("identity"));
+            assert_eq!(None, Encoding::from_token("compress"));
+        }
+
+        #[test]
+        fn parses_qvalues() {
+            assert_eq!(
+                AcceptEncoding {
+                    codings: Some(vec![
+                        ("gzip".to_owned(), 800),
+                        ("br".to_owned(), 1000),
+                        ("identity".to_owned(), 0),
+                        ("gzip".to_owned(), 5),
+                    ])
+                },
+                AcceptEncoding::parse(Some(
+                    "GZIP;q=0.8, br , identity;Q=0, x-gzip;q=0.005, zstd;q=2, deflate;q=0.1234, ;q=1"
+                ))
+            );
+        }
+
+        #[test]
+        fn chooses_gzip() {
+            assert_eq!(Some(Encoding::Gzip), choose(Some("gzip")));
+            assert_eq!(Some(Encoding::Gzip), choose(Some("GZIP;q=0.5, compress")));
+            assert_eq!(Some(Encoding::Gzip), choose(Some("identity;q=0.5, gzip")));
+            assert_eq!(
+                Some(Encoding::Gzip),
+                choose(Some("identity;q=0, gzip;q=0.001"))
+            );
+        }
+
+        #[test]
+        fn prefers_identity_when_acceptable() {
+            assert_eq!(Some(Encoding::Identity), choose(None));
+            assert_eq!(Some(Encoding::Identity), choose(Some("")));
+            assert_eq!(Some(Encoding::Identity), choose(Some("compress")));
+            assert_eq!(Some(Encoding::Identity), choose(Some("gzip;q=0")));
+            assert_eq!(
+                Some(Encoding::Identity),
+                choose(Some("gzip;q=0.5, identity"))
+            );
+            // Not worth compressing, and the client doesn't mind
+            assert_eq!(
+                Some(Encoding::Identity),
+                AcceptEncoding::parse(Some("gzip")).choose(false)
+            );
+            // ...but it does mind
+            assert_eq!(
+                Some(Encoding::Gzip),
+                AcceptEncoding::parse(Some("gzip, identity;q=0")).choose(false)
+            );
+        }
+
+        #[test]
+        fn finds_nothing_acceptable() {
+            assert_eq!(None, choose(Some("identity;q=0")));
+            assert_eq!(None, choose(Some("*;q=0")));
+            assert_eq!(None, choose(Some("compress, *;q=0")));
+        }
+
+        #[cfg(feature = "brotli")]
+        #[test]
+        fn chooses_by_weight_then_preference() {
+            assert_eq!(Some(Encoding::Gzip), choose(Some("br;q=0.5, gzip")));
+            assert_eq!(Some(Encoding::Brotli), choose(Some("gzip, br")));
+        }
+
+        #[test]
+        fn round_trips_supported_encodings() {
+            use std::io::Read;
+            let body = b"Hello, world! ".repeat(10);
+            for encoding in Encoding::SUPPORTED {
+                let mut encoded = Vec::new();
+                encoding
+                    .encoder(Box::new(std::io::Cursor::new(body.clone())))
+                    .unwrap()
+                    .read_to_end(&mut encoded)
+                    .unwrap();
+                assert!(encoded.len() < body.len());
+                let mut decoded = Vec::new();
+                encoding
+                    .decoder(Box::new(std::io::Cursor::new(encoded)))
+                    .unwrap()
+                    .read_to_end(&mut decoded)
+                    .unwrap();
+                assert_eq!(body, decoded);
+            }
+        }
+    }
+}
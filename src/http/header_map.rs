@@ -0,0 +1,122 @@
+// https://www.rfc-editor.org/rfc/rfc9110#section-5
+// Field names are case-insensitive, and the same field can appear more than
+// once. Every field is kept, in the order it arrived and with the name as it
+// was sent, so nothing a client tells us is lost or mixed up with another
+// field. There are only ever a handful of them, so looking one up is a scan.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct HeaderMap {
+    fields: Vec<(String, String)>,
+}
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Adds a field, keeping any others with the same name
+    pub fn append(&mut self, name: impl AsRef<str>, value: impl Into<String>) {
+        self.fields.push((name.as_ref().to_owned(), value.into()));
+    }
+
+    pub fn remove(&mut self, name: impl AsRef<str>) {
+        self.fields
+            .retain(|(n, _)| !n.eq_ignore_ascii_case(name.as_ref()));
+    }
+
+    // The first value of the field
+    pub fn get(&self, name: impl AsRef<str>) -> Option<&str> {
+        self.get_all(name).next()
+    }
+
+    // Every value of the field, in the order they arrived
+    pub fn get_all(&self, name: impl AsRef<str>) -> impl Iterator<Item = &str> {
+        self.fields
+            .iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name.as_ref()))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn contains(&self, name: impl AsRef<str>) -> bool {
+        self.get(name).is_some()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    // https://www.rfc-editor.org/rfc/rfc9110#section-5.3
+    // For list-based fields, sending them more than once is the same as
+    // sending one with all the values separated by commas. This doesn't work
+    // for Set-Cookie, use get_all for that.
+    pub fn get_combined(&self, name: impl AsRef<str>) -> Option<String> {
+        let values = self.get_all(name).collect::<Vec<&str>>();
+        (!values.is_empty()).then(|| values.join(", "))
+    }
+}
+
+// https://www.rfc-editor.org/rfc/rfc9110#section-5.6.2
+pub fn is_tchar(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+// A field name is a token
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(is_tchar)
+}
+
+// https://www.rfc-editor.org/rfc/rfc9110#section-5.5
+// Control characters other than tab aren't allowed in a value. A CR or LF in
+// particular would end the field (or the head) early.
+pub fn is_valid_value(value: &[u8]) -> bool {
+    !value.iter().any(|&b| b.is_ascii_control() && b != b'\t')
+}
+
+impl<N: AsRef<str>, V: Into<String>> FromIterator<(N, V)> for HeaderMap {
+    fn from_iter<I: IntoIterator<Item = (N, V)>>(iter: I) -> Self {
+        let mut headers = Self::new();
+        for (name, value) in iter {
+            headers.append(name, value);
+        }
+        headers
+    }
+}
+
+impl<N: AsRef<str>, V: Into<String>, const L: usize> From<[(N, V); L]> for HeaderMap {
+    fn from(fields: [(N, V); L]) -> Self {
+        fields.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod header_map {
+        use crate::http::{HeaderMap, Headers};
+
+        #[test]
+        fn looks_up_names_case_insensitively() {
+            let headers = HeaderMap::from([("content-LENGTH", "3"), ("Host", "localhost")]);
+            assert_eq!(Some("3"), headers.get(Headers::ContentLength));
+            assert_eq!(Some("localhost"), headers.get("HOST"));
+            assert_eq!(None, headers.get("Authorization"));
+        }
+
+        #[test]
+        fn keeps_repeated_fields() {
+            let headers = HeaderMap::from([
+                ("Set-Cookie", "a=1"),
+                ("Accept-Encoding", "gzip"),
+                ("set-cookie", "b=2"),
+                ("Accept-Encoding", "deflate"),
+            ]);
+            assert_eq!(
+                vec!["a=1", "b=2"],
+                headers.get_all("Set-Cookie").collect::<Vec<&str>>()
+            );
+            assert_eq!(
+                Some("gzip, deflate".to_owned()),
+                headers.get_combined(Headers::AcceptEncoding)
+            );
+        }
+    }
+}
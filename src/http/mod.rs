@@ -1,16 +1,29 @@
+mod chunked;
+pub mod conditional;
+pub mod encoding;
+mod header_map;
+pub mod range;
 mod request;
 mod response;
+mod status;
+mod target;
 
-use std::fmt::Display;
+use std::{fmt::Display, time::Duration};
 
 pub use crate::errors::{ClientError, ServerError};
-pub use request::Request;
+pub use header_map::HeaderMap;
+pub use request::{Request, RequestLimits};
 pub use response::Response;
+pub use status::StatusCode;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Method {
     Get,
+    Head,
     Post,
+    Put,
+    Delete,
+    Options,
     Unknown,
     Unsupported,
 }
@@ -19,119 +32,241 @@ impl From<Option<&str>> for Method {
     fn from(o: Option<&str>) -> Self {
         match o {
             Some("GET") => Self::Get,
+            Some("HEAD") => Self::Head,
             Some("POST") => Self::Post,
+            Some("PUT") => Self::Put,
+            Some("DELETE") => Self::Delete,
+            Some("OPTIONS") => Self::Options,
             // Maybe tomorrow...
-            Some("PUT") | Some("PATCH") | Some("OPTIONS") | Some("HEAD") | Some("DELETE")
-            | Some("CONNECT") | Some("TRACE") => Self::Unsupported,
+            Some("PATCH") | Some("CONNECT") | Some("TRACE") => Self::Unsupported,
             _ => Self::Unknown,
         }
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
-pub enum Encoding {
-    Gzip,
-    Unknown,
-}
-
-impl From<&str> for Encoding {
-    fn from(value: &str) -> Self {
-        match value {
-            "gzip" => Self::Gzip,
-            _ => Self::Unknown,
-        }
-    }
-}
-
-#[derive(Debug)]
-pub enum StatusCode {
-    Ok,
-    Created,
-    NotFound,
-    ServerError,
-    ClientError,
-    NotImplemented,
-}
-
-impl Display for StatusCode {
+impl Display for Method {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Ok => write!(f, "200 OK"),
-            Self::Created => write!(f, "201 Created"),
-            Self::ClientError => write!(f, "400 Bad Request"),
-            Self::NotFound => write!(f, "404 Not Found"),
-            Self::ServerError => write!(f, "500 Internal Server Error"),
-            Self::NotImplemented => write!(f, "501 Not Implemented"),
+            Self::Get => write!(f, "GET"),
+            Self::Head => write!(f, "HEAD"),
+            Self::Post => write!(f, "POST"),
+            Self::Put => write!(f, "PUT"),
+            Self::Delete => write!(f, "DELETE"),
+            Self::Options => write!(f, "OPTIONS"),
+            Self::Unknown | Self::Unsupported => write!(f, ""),
         }
     }
 }
 
-// I can't just use an .into() on these, because of the _ in the from.
-// I would have to implement TryFrom and then account for the Error. I am on the fence about
-// this...
-// TODO: implement TryFrom?
-#[derive(Debug, Hash, PartialEq, Eq)]
+// https://www.rfc-editor.org/rfc/rfc9112#section-2.3 - the versions of
+// HTTP/1 we tell apart. Any later minor version is read as 1.1, which it has
+// to be compatible with.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+// The fields we know about and act on. Requests can carry any others, see
+// HeaderMap, and these can be used to look them up.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
 pub enum Headers {
     UserAgent,
     ContentLength,
     ContentEncoding,
     AcceptEncoding,
     ContentType,
-    Unknown,
+    Connection,
+    KeepAlive,
+    TransferEncoding,
+    Allow,
+    Range,
+    IfRange,
+    AcceptRanges,
+    ContentRange,
+    ETag,
+    LastModified,
+    IfMatch,
+    IfNoneMatch,
+    IfModifiedSince,
+    Expect,
+    Date,
+    Server,
+    Location,
+    Vary,
+    Host,
 }
 
-impl From<&str> for Headers {
-    fn from(value: &str) -> Self {
-        match value {
-            "User-Agent" => Self::UserAgent,
-            "Content-Length" => Self::ContentLength,
-            "Content-Encoding" => Self::ContentEncoding,
-            "Accept-Encoding" => Self::AcceptEncoding,
-            "Content-Type" => Self::ContentType,
-            _ => Self::Unknown,
+impl Headers {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::UserAgent => "User-Agent",
+            Self::ContentLength => "Content-Length",
+            Self::ContentEncoding => "Content-Encoding",
+            Self::AcceptEncoding => "Accept-Encoding",
+            Self::ContentType => "Content-Type",
+            Self::Connection => "Connection",
+            Self::KeepAlive => "Keep-Alive",
+            Self::TransferEncoding => "Transfer-Encoding",
+            Self::Allow => "Allow",
+            Self::Range => "Range",
+            Self::IfRange => "If-Range",
+            Self::AcceptRanges => "Accept-Ranges",
+            Self::ContentRange => "Content-Range",
+            Self::ETag => "ETag",
+            Self::LastModified => "Last-Modified",
+            Self::IfMatch => "If-Match",
+            Self::IfNoneMatch => "If-None-Match",
+            Self::IfModifiedSince => "If-Modified-Since",
+            Self::Expect => "Expect",
+            Self::Date => "Date",
+            Self::Server => "Server",
+            Self::Location => "Location",
+            Self::Vary => "Vary",
+            Self::Host => "Host",
         }
     }
 }
 
-impl From<String> for Headers {
-    fn from(value: String) -> Self {
-        Self::from(value.as_str())
+impl AsRef<str> for Headers {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Display for Headers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
     }
 }
 
-impl From<&String> for Headers {
-    fn from(value: &String) -> Self {
-        Self::from(value.to_owned())
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Connection {
+    KeepAlive,
+    Close,
+}
+
+impl From<&str> for Connection {
+    fn from(value: &str) -> Self {
+        // The header is a list of tokens (e.g. "keep-alive, Upgrade"), and
+        // "close" wins if it appears anywhere in it
+        if value
+            .split(',')
+            .any(|token| token.trim().eq_ignore_ascii_case("close"))
+        {
+            Self::Close
+        } else {
+            Self::KeepAlive
+        }
     }
 }
 
-// TODO: I don't think this is great...
-impl Display for Headers {
+impl Display for Connection {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::UserAgent => write!(f, "User-Agent"),
-            Self::ContentLength => write!(f, "Content-Length"),
-            Self::ContentEncoding => write!(f, "Content-Encoding"),
-            Self::AcceptEncoding => write!(f, "Accept-Encoding"),
-            Self::ContentType => write!(f, "Content-Type"),
-            Self::Unknown => write!(f, ""),
+            Self::KeepAlive => write!(f, "keep-alive"),
+            Self::Close => write!(f, "close"),
         }
     }
 }
 
+// The parameters advertised in the `Keep-Alive` header of a persistent
+// connection: how long we will wait for the next request, and how many more
+// requests we will serve before closing.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct KeepAlive {
+    pub timeout: Duration,
+    pub max: usize,
+}
+
+impl Display for KeepAlive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "timeout={}, max={}", self.timeout.as_secs(), self.max)
+    }
+}
+
 #[derive(Debug)]
 pub enum MimeType {
     PlainText,
     OctetStream,
+    // The parts are separated by the boundary string
+    MultipartByteRanges(String),
+    Other(String),
     Unknown,
 }
 
+impl MimeType {
+    // Guessed from the extension, for files, which don't carry their type.
+    // Anything we don't know is just bytes.
+    pub fn from_file_name(name: &str) -> Self {
+        let Some((_, extension)) = name.rsplit_once('.') else {
+            return Self::OctetStream;
+        };
+        let mime_type = match extension.to_ascii_lowercase().as_str() {
+            "txt" => return Self::PlainText,
+            "html" | "htm" => "text/html",
+            "css" => "text/css",
+            "csv" => "text/csv",
+            "js" | "mjs" => "text/javascript",
+            "json" => "application/json",
+            "xml" => "application/xml",
+            "svg" => "image/svg+xml",
+            "png" => "image/png",
+            "jpg" | "jpeg" => "image/jpeg",
+            "gif" => "image/gif",
+            "webp" => "image/webp",
+            "ico" => "image/x-icon",
+            "mp3" => "audio/mpeg",
+            "mp4" => "video/mp4",
+            "webm" => "video/webm",
+            "pdf" => "application/pdf",
+            "wasm" => "application/wasm",
+            "gz" | "tgz" => "application/gzip",
+            "zip" => "application/zip",
+            "zst" => "application/zstd",
+            "bz2" => "application/x-bzip2",
+            "xz" => "application/x-xz",
+            "7z" => "application/x-7z-compressed",
+            "rar" => "application/x-rar-compressed",
+            _ => return Self::OctetStream,
+        };
+        Self::Other(mime_type.to_owned())
+    }
+
+    // Formats that are compressed already, which gzip can't do much more for
+    pub fn is_compressed(&self) -> bool {
+        let Self::Other(mime_type) = self else {
+            return false;
+        };
+        let essence = mime_type.split(';').next().unwrap_or_default().trim();
+        let essence = essence.to_ascii_lowercase();
+        match essence.split_once('/') {
+            Some(("image", "svg+xml")) => false,
+            Some(("image" | "audio" | "video", _)) => true,
+            Some(("application", subtype)) => matches!(
+                subtype,
+                "gzip"
+                    | "zip"
+                    | "zstd"
+                    | "x-bzip2"
+                    | "x-xz"
+                    | "x-7z-compressed"
+                    | "x-rar-compressed"
+                    | "pdf"
+                    | "wasm"
+            ),
+            _ => false,
+        }
+    }
+}
+
 impl From<&str> for MimeType {
     fn from(value: &str) -> Self {
         match value {
             "text/plain" => Self::PlainText,
             "application/octet-stream" => Self::OctetStream,
-            _ => Self::Unknown,
+            "" => Self::Unknown,
+            _ => Self::Other(value.to_owned()),
         }
     }
 }
@@ -141,6 +276,10 @@ impl Display for MimeType {
         match self {
             Self::PlainText => write!(f, "text/plain"),
             Self::OctetStream => write!(f, "application/octet-stream"),
+            Self::MultipartByteRanges(boundary) => {
+                write!(f, "multipart/byteranges; boundary={boundary}")
+            }
+            Self::Other(mime_type) => write!(f, "{mime_type}"),
             Self::Unknown => write!(f, ""),
         }
     }
@@ -0,0 +1,145 @@
+use std::{fmt::Display, ops::Range};
+
+// More ranges than this in one request is more likely abuse than a client
+// resuming a download, so we just send the whole thing
+const MAX_RANGES: usize = 16;
+
+// https://www.rfc-editor.org/rfc/rfc9110#section-14.1.2
+#[derive(Debug, PartialEq)]
+pub enum ByteRange {
+    // bytes=0-499
+    FromTo(u64, u64),
+    // bytes=500-
+    From(u64),
+    // bytes=-500, the last 500 bytes
+    Suffix(u64),
+}
+
+impl ByteRange {
+    // The part of a representation of `len` bytes this range covers, if any
+    fn resolve(&self, len: u64) -> Option<Range<u64>> {
+        let (start, end) = match *self {
+            Self::FromTo(start, end) => (start, end.saturating_add(1).min(len)),
+            Self::From(start) => (start, len),
+            Self::Suffix(0) => return None,
+            Self::Suffix(suffix) => (len.saturating_sub(suffix), len),
+        };
+        (start < end).then_some(start..end)
+    }
+}
+
+// A Range header we can't make sense of is ignored rather than rejected, so
+// this gives None for anything but a well-formed set of byte ranges
+pub fn parse(header: &str) -> Option<Vec<ByteRange>> {
+    let (unit, ranges) = header.split_once('=')?;
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return None;
+    }
+    let ranges = ranges
+        .split(',')
+        .map(str::trim)
+        .filter(|r| !r.is_empty())
+        .map(|r| {
+            let (start, end) = r.split_once('-')?;
+            match (start.trim(), end.trim()) {
+                ("", suffix) => suffix.parse().ok().map(ByteRange::Suffix),
+                (start, "") => start.parse().ok().map(ByteRange::From),
+                (start, end) => {
+                    let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+                    (start <= end).then_some(ByteRange::FromTo(start, end))
+                }
+            }
+        })
+        .collect::<Option<Vec<ByteRange>>>()?;
+    (!ranges.is_empty() && ranges.len() <= MAX_RANGES).then_some(ranges)
+}
+
+// The satisfiable ranges for a representation of `len` bytes, in order and
+// with any that overlap or touch merged. Otherwise "bytes=0-,0-,0-" would
+// have us send the whole file once for every range asked for
+// (https://www.rfc-editor.org/rfc/rfc9110#section-14.2). If this is empty,
+// the answer is 416 Range Not Satisfiable.
+pub fn resolve(ranges: &[ByteRange], len: u64) -> Vec<Range<u64>> {
+    let mut resolved = ranges
+        .iter()
+        .filter_map(|r| r.resolve(len))
+        .collect::<Vec<Range<u64>>>();
+    resolved.sort_by_key(|r| r.start);
+    let mut merged: Vec<Range<u64>> = Vec::with_capacity(resolved.len());
+    for range in resolved {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ContentRange {
+    Satisfied(Range<u64>, u64),
+    Unsatisfied(u64),
+}
+
+impl Display for ContentRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Satisfied(range, len) => {
+                write!(f, "bytes {}-{}/{len}", range.start, range.end - 1)
+            }
+            Self::Unsatisfied(len) => write!(f, "bytes */{len}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod range {
+        use crate::http::range::{parse, resolve, ByteRange, ContentRange};
+
+        #[test]
+        fn parses_ranges() {
+            assert_eq!(
+                Some(vec![
+                    ByteRange::FromTo(0, 499),
+                    ByteRange::From(9500),
+                    ByteRange::Suffix(500)
+                ]),
+                parse("bytes=0-499, 9500-,-500")
+            );
+        }
+
+        #[test]
+        fn ignores_invalid_ranges() {
+            for header in ["bytes=", "items=0-5", "bytes=5-0", "bytes=a-b", "bytes=5"] {
+                assert_eq!(None, parse(header));
+            }
+        }
+
+        #[test]
+        fn resolves_ranges() {
+            let ranges = parse("bytes=0-4,8-100,-3,20-").unwrap();
+            assert_eq!(vec![0..5, 7..10], resolve(&ranges, 10));
+            assert!(resolve(&parse("bytes=10-").unwrap(), 10).is_empty());
+        }
+
+        #[test]
+        fn merges_overlapping_ranges() {
+            let resolve = |header| resolve(&parse(header).unwrap(), 10);
+            assert_eq!(vec![0..10], resolve("bytes=0-,0-,0-,0-"));
+            // Touching is as good as overlapping
+            assert_eq!(vec![0..10], resolve("bytes=5-9,0-4"));
+            assert_eq!(vec![0..2, 7..10], resolve("bytes=-2,0-1,7-8"));
+        }
+
+        #[test]
+        fn displays_content_range() {
+            assert_eq!(
+                "bytes 0-4/10",
+                ContentRange::Satisfied(0..5, 10).to_string()
+            );
+            assert_eq!("bytes */10", ContentRange::Unsatisfied(10).to_string());
+        }
+    }
+}
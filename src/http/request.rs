@@ -1,138 +1,797 @@
-use std::{
-    collections::HashMap,
-    io::{BufRead, BufReader, Read},
-};
+use std::io::{BufRead, BufReader, Cursor, Read};
 
 use crate::{
-    errors::{AppError, ClientError},
-    router::Route,
+    constants::{MAX_BODY_SIZE, MAX_HEADER_COUNT, MAX_HEADER_SIZE, MAX_REQUEST_LINE_LEN},
+    errors::{AppError, ClientError, ServerError},
     Result,
 };
 
-use super::{Headers, Method};
-
-fn get_path_parts(s: &str) -> Vec<String> {
-    s.split("/")
-        .filter(|s| !s.is_empty())
-        .map(|s| s.to_owned())
-        .collect()
-}
+use super::{
+    chunked, encoding::Encoding, header_map, target, Connection, HeaderMap, Headers, Method,
+    Version,
+};
 
 #[derive(Debug, PartialEq)]
 pub struct Request {
     pub method: Method,
-    pub route: Route,
+    pub version: Version,
     // https://steveklabnik.com/writing/when-should-i-use-string-vs-str/
+    // The path with its percent-encoding undone and dot segments resolved,
+    // split into path_parts. The raw forms are as the client sent them.
     pub path: String,
-    pub headers: HashMap<Headers, String>,
+    pub raw_path: String,
+    pub raw_query: Option<String>,
+    pub query: Vec<(String, String)>,
+    // Taken from the path by the pattern of the route it matched
+    pub params: Vec<(String, String)>,
+    pub headers: HeaderMap,
     pub body: Vec<u8>,
     pub path_parts: Vec<String>,
 }
 
 impl Request {
-    pub fn get_header(&self, header: Headers) -> Option<&String> {
-        let header_val = self.headers.get(&header);
-        header_val
+    // A field sent more than once comes back as one comma separated list
+    pub fn get_header(&self, header: impl AsRef<str>) -> Option<String> {
+        self.headers.get_combined(header)
+    }
+
+    // HTTP/1.1 connections are persistent unless the client asks otherwise,
+    // HTTP/1.0 ones only if the client asks for it
+    // https://www.rfc-editor.org/rfc/rfc9112#section-9.3
+    pub fn connection(&self) -> Connection {
+        if self.has_faulty_framing() {
+            return Connection::Close;
+        }
+        let header = self.get_header(Headers::Connection).unwrap_or_default();
+        let keep_alive = header
+            .split(',')
+            .any(|token| token.trim().eq_ignore_ascii_case("keep-alive"));
+        match self.version {
+            Version::Http10 if !keep_alive => Connection::Close,
+            _ => Connection::from(header.as_str()),
+        }
+    }
+
+    // https://www.rfc-editor.org/rfc/rfc9112#section-6.1 - Transfer-Encoding
+    // isn't HTTP/1.0, so whatever sent it there may not agree with us on
+    // where the body ends. The framing is faulty and the connection is closed
+    // after the response.
+    fn has_faulty_framing(&self) -> bool {
+        self.version == Version::Http10 && self.headers.contains(Headers::TransferEncoding)
+    }
+
+    // The request-target as it was sent, which is what goes in the logs
+    pub fn target(&self) -> String {
+        match &self.raw_query {
+            Some(query) => format!("{}?{query}", self.raw_path),
+            None => self.raw_path.clone(),
+        }
+    }
+
+    // The value the path gave the named parameter of the route's pattern
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
     }
 }
 
-impl<R: Read> TryFrom<&mut BufReader<R>> for Request {
-    type Error = AppError;
-    fn try_from(buf: &mut BufReader<R>) -> Result<Self>
-    where
-        R: Read,
-    {
-        let mut start_line = String::new();
-        let _ = buf.read_line(&mut start_line)?;
-        let mut start_parts = start_line.split_whitespace();
-        let method = Method::from(start_parts.next());
-        let path = match start_parts.next() {
-            Some(s) => s.to_owned(),
-            None => {
+// Nothing routes on the query yet, this is for handlers that want it
+#[allow(dead_code)]
+impl Request {
+    // The first value given for `name`
+    pub fn query<'a>(&'a self, name: &'a str) -> Option<&'a str> {
+        self.query_all(name).next()
+    }
+
+    // Every value given for `name`, in the order they were sent
+    pub fn query_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.query
+            .iter()
+            .filter(move |(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+// How much of a request we are willing to read. Each is checked as the
+// request comes in, so a client can't make us hold more than this in memory.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestLimits {
+    // https://www.rfc-editor.org/rfc/rfc9112#section-3 - 414 URI Too Long
+    pub request_line: usize,
+    // All the field lines together, and how many of them, 431 Request
+    // Header Fields Too Large
+    pub header_size: usize,
+    pub header_count: usize,
+    // The body as it is framed on the wire, 413 Content Too Large
+    pub body: u64,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        Self {
+            request_line: MAX_REQUEST_LINE_LEN,
+            header_size: MAX_HEADER_SIZE,
+            header_count: MAX_HEADER_COUNT,
+            body: MAX_BODY_SIZE,
+        }
+    }
+}
+
+// One line of the head, without its line ending, and how many bytes it took
+// up with it. The head ends with an empty line, so running out of input
+// before then is a bad request. A line of more than `max` bytes (line ending
+// included) is `too_long`.
+// https://www.rfc-editor.org/rfc/rfc9112#section-2.2 - lines end with CRLF,
+// but a bare LF is allowed unless we are being strict. Servers disagreeing
+// about where a line ends is one way requests get smuggled.
+fn read_head_line<R: Read>(
+    buf: &mut BufReader<R>,
+    max: usize,
+    too_long: ClientError,
+    strict: bool,
+) -> Result<(Vec<u8>, usize)> {
+    let mut line = Vec::new();
+    let read = buf.take(max as u64).read_until(b'\n', &mut line)?;
+    if !line.ends_with(b"\n") {
+        return Err(if line.len() == max {
+            too_long
+        } else {
+            ClientError::BadRequest
+        }
+        .into());
+    }
+    line.pop();
+    if line.ends_with(b"\r") {
+        line.pop();
+    } else if strict {
+        return Err(ClientError::BadRequest.into());
+    }
+    Ok((line, read))
+}
+
+// https://www.rfc-editor.org/rfc/rfc9112#section-3
+// request-line = method SP request-target SP HTTP-version
+// The method, the request-target in origin-form and the version. Strictly
+// that's single spaces and nothing else, otherwise any whitespace will do
+// and the version can be left out, as HTTP/0.9 did.
+fn parse_request_line(line: &str, strict: bool) -> Result<(Method, String, Version)> {
+    let parts = if strict {
+        line.split(' ').collect::<Vec<&str>>()
+    } else {
+        line.split_whitespace().collect()
+    };
+    let (method, target, version) = match parts.as_slice() {
+        [method, target, version] if strict => {
+            if method.is_empty()
+                || !method.bytes().all(header_map::is_tchar)
+                || !target.bytes().all(|b| b.is_ascii_graphic())
+            {
                 return Err(ClientError::BadRequest.into());
             }
-        };
-        let path_parts = get_path_parts(path.as_str());
+            (*method, *target, parse_version(version)?)
+        }
+        [method, target, version] if !strict => (*method, *target, parse_version(version)?),
+        // The oldest version we speak
+        [method, target] if !strict => (*method, *target, Version::Http10),
+        _ => return Err(ClientError::BadRequest.into()),
+    };
+    let method = Method::from(Some(method));
+    Ok((method, origin_form(method, target, strict)?, version))
+}
+
+// HTTP-version = "HTTP/" DIGIT "." DIGIT
+// A version we can read but whose major version isn't 1 is one we don't
+// speak (https://www.rfc-editor.org/rfc/rfc9110#section-15.6.6).
+fn parse_version(version: &str) -> Result<Version> {
+    match version.strip_prefix("HTTP/").map(str::as_bytes) {
+        Some(b"1.0") => Ok(Version::Http10),
+        Some([b'1', b'.', minor]) if minor.is_ascii_digit() => Ok(Version::Http11),
+        Some([major, b'.', minor]) if major.is_ascii_digit() && minor.is_ascii_digit() => {
+            Err(ServerError::HttpVersionNotSupported.into())
+        }
+        _ => Err(ClientError::BadRequest.into()),
+    }
+}
 
-        let route = if path_parts.is_empty() {
-            Route::Empty
+// https://www.rfc-editor.org/rfc/rfc9112#section-3.2
+// A server has to take an absolute URI (absolute-form) as well as a path, so
+// the scheme and authority are dropped. "*" is only for OPTIONS on the
+// server as a whole (asterisk-form), and there's no CONNECT
+// (authority-form). Anything else only gets through if we're not strict.
+fn origin_form(method: Method, target: &str, strict: bool) -> Result<String> {
+    let scheme = target.split_once("://").filter(|(scheme, _)| {
+        scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https")
+    });
+    if let Some((_, rest)) = scheme {
+        let path = rest.find(['/', '?']).map_or("", |i| &rest[i..]);
+        return Ok(if path.starts_with('/') {
+            path.to_owned()
         } else {
-            Route::from(&path_parts[0])
-        };
+            format!("/{path}")
+        });
+    }
+    match target {
+        "*" if method == Method::Options => Ok(target.to_owned()),
+        _ if target.starts_with('/') || !strict => Ok(target.to_owned()),
+        _ => Err(ClientError::BadRequest.into()),
+    }
+}
+
+// https://www.rfc-editor.org/rfc/rfc9112#section-5
+// field-line = field-name ":" OWS field-value OWS
+// The name is split off at the first colon, as values often have colons of
+// their own (Host: localhost:4221). There can't be any whitespace between the
+// name and the colon.
+fn parse_field_line(line: &[u8]) -> Result<(String, String)> {
+    let colon = line
+        .iter()
+        .position(|&b| b == b':')
+        .ok_or(ClientError::BadRequest)?;
+    let (name, value) = (&line[..colon], &line[colon + 1..]);
+    if name.is_empty() || !name.iter().all(|&b| header_map::is_tchar(b)) {
+        return Err(ClientError::BadRequest.into());
+    }
+    Ok((
+        String::from_utf8_lossy(name).into_owned(),
+        field_value(value)?,
+    ))
+}
+
+// Leading and trailing whitespace isn't part of the value. Control characters
+// (a stray CR or NUL in particular) are rejected rather than passed on to
+// whatever reads the value.
+fn field_value(value: &[u8]) -> Result<String> {
+    if !header_map::is_valid_value(value) {
+        return Err(ClientError::BadRequest.into());
+    }
+    let is_ows = |b: &u8| *b == b' ' || *b == b'\t';
+    let start = value.iter().position(|b| !is_ows(b)).unwrap_or(value.len());
+    let end = value
+        .iter()
+        .rposition(|b| !is_ows(b))
+        .map_or(start, |end| end + 1);
+    // Anything that isn't ASCII is opaque to us (obs-text)
+    Ok(String::from_utf8_lossy(&value[start..end]).into_owned())
+}
+
+// How the length of the request body is determined
+// https://www.rfc-editor.org/rfc/rfc9112#section-6.3
+enum BodyFraming {
+    Empty,
+    Length(u64),
+    Chunked,
+}
+
+// https://www.rfc-editor.org/rfc/rfc9112#section-6.3
+// Content-Length = 1*DIGIT. Sent more than once, or as a list, the values
+// all have to be the same, and when we're strict there can only be one. A
+// length we can't read is never guessed at, as a server that read it
+// differently would see a different request after the body.
+fn content_length(headers: &HeaderMap, strict: bool) -> Result<u64> {
+    let values = headers
+        .get_all(Headers::ContentLength)
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .collect::<Vec<&str>>();
+    if strict && values.len() > 1 {
+        return Err(ClientError::BadRequest.into());
+    }
+    let mut lengths = values.iter().map(|v| {
+        if v.is_empty() || !v.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ClientError::BadRequest);
+        }
+        v.parse::<u64>().map_err(|_| ClientError::BadRequest)
+    });
+    let len = lengths.next().ok_or(ClientError::BadRequest)??;
+    for other in lengths {
+        if other? != len {
+            return Err(ClientError::BadRequest.into());
+        }
+    }
+    Ok(len)
+}
+
+impl BodyFraming {
+    fn try_from_headers(headers: &HeaderMap, strict: bool) -> Result<Self> {
+        match (
+            headers.get_combined(Headers::ContentLength),
+            headers.get_combined(Headers::TransferEncoding),
+        ) {
+            // https://www.rfc-editor.org/rfc/rfc9112#section-6.1 - a sender
+            // can't use both, and it's a classic way to smuggle requests
+            (Some(_), Some(_)) => Err(ClientError::BadRequest.into()),
+            (None, Some(codings)) => {
+                let codings = codings.split(',').map(str::trim).collect::<Vec<&str>>();
+                match codings.as_slice() {
+                    [coding] if coding.eq_ignore_ascii_case("chunked") => Ok(Self::Chunked),
+                    // The length of the body can only be known if chunked is last
+                    [.., last] if last.eq_ignore_ascii_case("chunked") => {
+                        Err(ServerError::NotImplemented.into())
+                    }
+                    _ => Err(ClientError::BadRequest.into()),
+                }
+            }
+            (Some(_), None) => Ok(Self::Length(content_length(headers, strict)?)),
+            (None, None) => Ok(Self::Empty),
+        }
+    }
+}
 
-        let mut headers = HashMap::new();
+impl Request {
+    // The request line and headers, leaving the body on the reader. This is
+    // enough to decide whether we want the body at all (see Expect). When
+    // `strict`, anything that another server might read differently is
+    // rejected (see read_head_line, parse_request_line and content_length).
+    pub fn read_head<R: Read>(
+        buf: &mut BufReader<R>,
+        limits: &RequestLimits,
+        strict: bool,
+    ) -> Result<Self> {
+        let (start_line, _) =
+            read_head_line(buf, limits.request_line, ClientError::UriTooLong, strict)?;
+        let start_line = String::from_utf8_lossy(&start_line).into_owned();
+        let (method, request_target, version) = parse_request_line(&start_line, strict)?;
+        // https://www.rfc-editor.org/rfc/rfc9112#section-3.2.1
+        // origin-form = absolute-path [ "?" query ]
+        let (raw_path, raw_query) = match request_target.split_once('?') {
+            Some((path, query)) => (path.to_owned(), Some(query.to_owned())),
+            None => (request_target.to_owned(), None),
+        };
+        // OPTIONS * is about the server as a whole, which is what / is too
+        let path_parts = match raw_path.as_str() {
+            "*" => Vec::new(),
+            raw_path => target::path_segments(raw_path)?,
+        };
+        let path = format!("/{}", path_parts.join("/"));
+        let query = match &raw_query {
+            Some(raw_query) => target::query_params(raw_query)?,
+            None => Vec::new(),
+        };
 
+        let mut fields: Vec<(String, String)> = Vec::new();
+        // Whatever is left of header_size is as long as the next line can be
+        let mut header_size = limits.header_size;
         loop {
-            let mut header_line = String::new();
-            let _ = buf.read_line(&mut header_line)?;
-            let trimmed_header_line = header_line.trim();
-            if trimmed_header_line.is_empty() {
-                // I think we have reached the body at this point
-                break;
+            let (line, read) = read_head_line(
+                buf,
+                header_size,
+                ClientError::RequestHeaderFieldsTooLarge,
+                strict,
+            )?;
+            header_size -= read;
+            match line.first() {
+                // The empty line between the head and the body
+                None => break,
+                // https://www.rfc-editor.org/rfc/rfc9112#section-5.2 - a line
+                // starting with whitespace continues the previous field's
+                // value (obs-fold), which is the same as a single space. It's
+                // obsolete, so strictly it's rejected.
+                Some(b' ' | b'\t') if strict => return Err(ClientError::BadRequest.into()),
+                Some(b' ' | b'\t') => {
+                    let (_, value) = fields.last_mut().ok_or(ClientError::BadRequest)?;
+                    let folded = field_value(&line)?;
+                    if !folded.is_empty() {
+                        value.push(' ');
+                        value.push_str(&folded);
+                    }
+                }
+                Some(_) if fields.len() == limits.header_count => {
+                    return Err(ClientError::RequestHeaderFieldsTooLarge.into())
+                }
+                Some(_) => fields.push(parse_field_line(&line)?),
             }
-            let key_value = trimmed_header_line
-                .split_terminator(":")
-                .collect::<Vec<&str>>();
-            let key = Headers::from(key_value[0]);
-            let raw_value = key_value[1].trim();
-            let concat_parts = raw_value.replace(", ", ",");
-            headers
-                .entry(key)
-                // in-place mutation
-                .and_modify(|val| {
-                    *val = format!("{val},{concat_parts}");
-                })
-                .or_insert(concat_parts.to_owned());
         }
+        let headers = fields.into_iter().collect::<HeaderMap>();
 
-        let mut body_buf: Vec<u8> = vec![];
+        // https://www.rfc-editor.org/rfc/rfc9112#section-3.2 - HTTP/1.1
+        // requests have to say which host they are for, and only once
+        let hosts = headers.get_all(Headers::Host).count();
+        if hosts > 1 || (hosts == 0 && version == Version::Http11) {
+            return Err(ClientError::BadRequest.into());
+        }
+        if strict && version == Version::Http10 && headers.contains(Headers::TransferEncoding) {
+            return Err(ClientError::BadRequest.into());
+        }
 
-        if route == Route::Echo && path_parts.len() > 1 {
-            body_buf.extend(path_parts[1].as_bytes());
-        } else {
-            // If there's no content length, do not attempt to parse the body
-            if let Some(len) = headers.get(&Headers::ContentLength) {
-                let len = len.parse::<u64>()?;
-                buf.take(len).read_to_end(&mut body_buf)?;
+        // Find out now if we couldn't or wouldn't read the body, rather than
+        // after telling the client to go ahead and send it
+        if let BodyFraming::Length(len) = BodyFraming::try_from_headers(&headers, strict)? {
+            if len > limits.body {
+                return Err(ClientError::ContentTooLarge.into());
             }
         }
 
         Ok(Self {
-            route,
             path,
+            raw_path,
+            raw_query,
+            query,
+            params: Vec::new(),
             method,
+            version,
             headers,
-            body: body_buf,
+            body: Vec::new(),
             path_parts,
         })
     }
+
+    // If there's no content length or transfer coding, do not attempt to
+    // parse the body. If there is, it has to be consumed even when we ignore
+    // it, or the next request on the connection would start in the middle of
+    // it
+    pub fn read_body<R: Read>(
+        &mut self,
+        buf: &mut BufReader<R>,
+        limits: &RequestLimits,
+        strict: bool,
+    ) -> Result<()> {
+        let mut body_buf: Vec<u8> = vec![];
+
+        match BodyFraming::try_from_headers(&self.headers, strict)? {
+            BodyFraming::Chunked => body_buf = chunked::decode(buf, limits.body, strict)?,
+            // https://www.rfc-editor.org/rfc/rfc9112#section-8 - the client
+            // closing before the whole body is here leaves it incomplete
+            BodyFraming::Length(len) => {
+                if buf.take(len).read_to_end(&mut body_buf)? as u64 != len {
+                    return Err(ClientError::BadRequest.into());
+                }
+            }
+            BodyFraming::Empty => {}
+        }
+
+        self.body = body_buf;
+        Ok(())
+    }
+}
+
+impl Request {
+    // https://www.rfc-editor.org/rfc/rfc9110#section-8.4 - undoes the content
+    // codings the client applied, the last one first, so that handlers get
+    // the body as it was before it was compressed. No more than `limit` bytes
+    // are let out, as a small body can decompress to something huge.
+    pub fn decode_body(&mut self, limit: u64) -> Result<()> {
+        let Some(codings) = self.get_header(Headers::ContentEncoding) else {
+            return Ok(());
+        };
+        let encodings = codings
+            .split(',')
+            .filter(|c| !c.trim().is_empty())
+            .map(|c| Encoding::from_token(c).ok_or(ClientError::UnsupportedMediaType))
+            .collect::<std::result::Result<Vec<Encoding>, ClientError>>()?;
+
+        let mut reader: Box<dyn Read> = Box::new(Cursor::new(std::mem::take(&mut self.body)));
+        for encoding in encodings.iter().rev() {
+            reader = encoding
+                .decoder(reader)
+                .map_err(|_| ClientError::BadRequest)?;
+        }
+        let mut body = Vec::new();
+        reader
+            .take(limit.saturating_add(1))
+            .read_to_end(&mut body)
+            .map_err(|_| ClientError::BadRequest)?;
+        if body.len() as u64 > limit {
+            return Err(ClientError::ContentTooLarge.into());
+        }
+
+        self.body = body;
+        self.headers.remove(Headers::ContentEncoding);
+        Ok(())
+    }
+}
+
+impl<R: Read> TryFrom<&mut BufReader<R>> for Request {
+    type Error = AppError;
+    fn try_from(buf: &mut BufReader<R>) -> Result<Self>
+    where
+        R: Read,
+    {
+        let limits = RequestLimits::default();
+        let mut req = Self::read_head(buf, &limits, false)?;
+        req.read_body(buf, &limits, false)?;
+        Ok(req)
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
     mod request {
-        use crate::errors::{AppError, ClientError};
-        use crate::http::request::{Method::Get, Request};
-        use crate::router::Route::Echo;
-        use std::{collections::HashMap, io::BufReader};
+        use crate::errors::{AppError, ClientError, ServerError};
+        use crate::http::request::{Method::Get, Request, RequestLimits};
+        use crate::http::Connection;
+        use crate::http::{HeaderMap, Headers, Version};
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::{BufReader, Write};
 
         #[test]
         fn handles_http_request() {
-            let req = b"GET /echo/abc HTTP/1.1\r\n\r\n";
+            let req = b"GET /echo/abc HTTP/1.1\r\nHost: localhost\r\n\r\n";
             let mut req_slice = req.as_slice();
             let mut req_buf = BufReader::new(&mut req_slice);
+            let mut headers = HeaderMap::new();
+            headers.append(Headers::Host, "localhost");
             let expected = Request {
                 method: Get,
-                route: Echo,
+                version: Version::Http11,
                 path: "/echo/abc".to_owned(),
+                raw_path: "/echo/abc".to_owned(),
+                raw_query: None,
+                query: Vec::new(),
+                params: Vec::new(),
                 path_parts: vec!["echo".to_owned(), "abc".to_owned()],
-                body: b"abc".to_vec(),
-                headers: HashMap::new(),
+                body: Vec::new(),
+                headers,
             };
             assert_eq!(expected, Request::try_from(&mut req_buf).unwrap());
         }
 
+        #[test]
+        fn keeps_every_header() {
+            let req = b"GET / HTTP/1.1\r\nHost: localhost\r\nCookie: a=1\r\naccept-encoding: gzip\r\nCookie: b=2\r\nAccept-Encoding: br\r\n\r\n";
+            let mut req_slice = req.as_slice();
+            let mut req_buf = BufReader::new(&mut req_slice);
+            let req = Request::try_from(&mut req_buf).unwrap();
+            assert_eq!(Some("localhost"), req.headers.get("host"));
+            assert_eq!(
+                vec!["a=1", "b=2"],
+                req.headers.get_all("Cookie").collect::<Vec<&str>>()
+            );
+            assert_eq!(
+                Some("gzip, br".to_owned()),
+                req.get_header(Headers::AcceptEncoding)
+            );
+        }
+
+        #[test]
+        fn parses_header_lines() {
+            let req = b"GET / HTTP/1.1\r\nhost:localhost:4221\r\nuser-agent: \t foo/1.0 \t\r\nX-Folded: a,\r\n  \tb\r\nX-Empty:\r\n\r\n";
+            let mut req_slice = req.as_slice();
+            let mut req_buf = BufReader::new(&mut req_slice);
+            let req = Request::try_from(&mut req_buf).unwrap();
+            assert_eq!(Some("localhost:4221"), req.headers.get("Host"));
+            assert_eq!(Some("foo/1.0"), req.headers.get(Headers::UserAgent));
+            assert_eq!(Some("a, b"), req.headers.get("x-folded"));
+            assert_eq!(Some(""), req.headers.get("x-empty"));
+        }
+
+        #[test]
+        fn rejects_malformed_header_lines() {
+            for line in [
+                b"No colon here".as_slice(),
+                b": no name",
+                b"Host : localhost",
+                b"Bad Name: x",
+                b"X-Test: a\rb",
+                b"X-Test: a\x00b",
+                b" Folded: without a field",
+            ] {
+                let req = [
+                    b"GET / HTTP/1.1\r\n".as_slice(),
+                    line,
+                    b"\r\nHost: localhost\r\n\r\n",
+                ]
+                .concat();
+                let mut req_slice = req.as_slice();
+                let mut req_buf = BufReader::new(&mut req_slice);
+                assert_eq!(
+                    AppError::Client(ClientError::BadRequest),
+                    Request::try_from(&mut req_buf).unwrap_err()
+                );
+            }
+        }
+
+        #[test]
+        fn decodes_request_target() {
+            let req = b"GET /echo/a%20b?x=1&y=%C3%A9&x=2 HTTP/1.1\r\nHost: localhost\r\n\r\n";
+            let mut req_slice = req.as_slice();
+            let mut req_buf = BufReader::new(&mut req_slice);
+            let req = Request::try_from(&mut req_buf).unwrap();
+            assert_eq!("/echo/a b", req.path);
+            assert_eq!(vec!["echo", "a b"], req.path_parts);
+            assert_eq!(Some("1"), req.query("x"));
+            assert_eq!(vec!["1", "2"], req.query_all("x").collect::<Vec<&str>>());
+            assert_eq!(Some("\u{e9}"), req.query("y"));
+            assert_eq!(None, req.query("z"));
+            assert_eq!("/echo/a%20b?x=1&y=%C3%A9&x=2", req.target());
+        }
+
+        #[test]
+        fn rejects_invalid_percent_encoding() {
+            for target in ["/files/a%2", "/files/a%zz", "/files/%ff", "/echo/a?b=%"] {
+                let req = format!("GET {target} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+                let mut req_slice = req.as_bytes();
+                let mut req_buf = BufReader::new(&mut req_slice);
+                assert_eq!(
+                    AppError::Client(ClientError::BadRequest),
+                    Request::try_from(&mut req_buf).unwrap_err()
+                );
+            }
+        }
+
+        #[test]
+        fn limits_header_size_with_line_endings() {
+            // 17 + 8 + 8 + 2 = 35 bytes after the request line, CRLFs included
+            let req = b"GET / HTTP/1.1\r\nHost: localhost\r\nA: 123\r\nB: 456\r\n\r\n";
+            let read = |header_size| {
+                let limits = RequestLimits {
+                    header_size,
+                    ..RequestLimits::default()
+                };
+                let mut req_slice = req.as_slice();
+                Request::read_head(&mut BufReader::new(&mut req_slice), &limits, false)
+            };
+            assert!(read(35).is_ok());
+            assert_eq!(
+                AppError::Client(ClientError::RequestHeaderFieldsTooLarge),
+                read(34).unwrap_err()
+            );
+        }
+
+        #[test]
+        fn rejects_truncated_head() {
+            let req = b"GET / HTTP/1.1\r\nHost: localhost\r\n";
+            let mut req_slice = req.as_slice();
+            let mut req_buf = BufReader::new(&mut req_slice);
+            assert_eq!(
+                AppError::Client(ClientError::BadRequest),
+                Request::try_from(&mut req_buf).unwrap_err()
+            );
+        }
+
+        fn gzip(data: &[u8]) -> Vec<u8> {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        }
+
+        fn upload(content_encoding: &str, body: &[u8]) -> Request {
+            let req = [
+                format!("POST /files/abc HTTP/1.1\r\nHost: localhost\r\nContent-Encoding: {content_encoding}\r\nContent-Length: {}\r\n\r\n", body.len()).as_bytes(),
+                body,
+            ]
+            .concat();
+            let mut req_slice = req.as_slice();
+            let mut req_buf = BufReader::new(&mut req_slice);
+            Request::try_from(&mut req_buf).unwrap()
+        }
+
+        #[test]
+        fn decodes_body() {
+            // Two gzip members back to back, as when compressed logs are appended
+            let body = [gzip(b"line 1\n"), gzip(b"line 2\n")].concat();
+            let mut req = upload("gzip, identity", &body);
+            req.decode_body(1024).unwrap();
+            assert_eq!(b"line 1\nline 2\n".to_vec(), req.body);
+            assert_eq!(None, req.get_header(Headers::ContentEncoding));
+        }
+
+        #[test]
+        fn rejects_undecodable_body() {
+            for (content_encoding, body, limit, err) in [
+                (
+                    "compress",
+                    b"abc".to_vec(),
+                    1024,
+                    ClientError::UnsupportedMediaType,
+                ),
+                ("gzip", b"abc".to_vec(), 1024, ClientError::BadRequest),
+                // A megabyte of zeros compresses to next to nothing
+                (
+                    "gzip",
+                    gzip(&vec![0; 1024 * 1024]),
+                    1024,
+                    ClientError::ContentTooLarge,
+                ),
+            ] {
+                let mut req = upload(content_encoding, &body);
+                assert_eq!(AppError::Client(err), req.decode_body(limit).unwrap_err());
+            }
+        }
+
+        #[test]
+        fn defaults_to_keep_alive() {
+            let req = b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+            let mut req_slice = req.as_slice();
+            let mut req_buf = BufReader::new(&mut req_slice);
+            let req = Request::try_from(&mut req_buf).unwrap();
+            assert_eq!(Connection::KeepAlive, req.connection());
+        }
+
+        #[test]
+        fn handles_connection_close() {
+            let req = b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+            let mut req_slice = req.as_slice();
+            let mut req_buf = BufReader::new(&mut req_slice);
+            let req = Request::try_from(&mut req_buf).unwrap();
+            assert_eq!(Connection::Close, req.connection());
+        }
+
+        #[test]
+        fn closes_http_1_0_by_default() {
+            for (req, connection) in [
+                (b"GET / HTTP/1.0\r\n\r\n".as_slice(), Connection::Close),
+                (
+                    b"GET / HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n",
+                    Connection::KeepAlive,
+                ),
+            ] {
+                let mut req_slice = req;
+                let mut req_buf = BufReader::new(&mut req_slice);
+                let req = Request::try_from(&mut req_buf).unwrap();
+                assert_eq!(Version::Http10, req.version);
+                assert_eq!(connection, req.connection());
+            }
+        }
+
+        #[test]
+        fn parses_version() {
+            for (version, expected) in [
+                ("HTTP/1.0", Ok(Version::Http10)),
+                ("HTTP/1.1", Ok(Version::Http11)),
+                // A later 1.x is understood as 1.1
+                ("HTTP/1.2", Ok(Version::Http11)),
+                (
+                    "HTTP/2.0",
+                    Err(AppError::Server(ServerError::HttpVersionNotSupported)),
+                ),
+                ("HTTP/1", Err(AppError::Client(ClientError::BadRequest))),
+                ("http/1.1", Err(AppError::Client(ClientError::BadRequest))),
+                ("HTTP/1.10", Err(AppError::Client(ClientError::BadRequest))),
+            ] {
+                let req = format!("GET / {version}\r\nHost: localhost\r\n\r\n");
+                let mut req_slice = req.as_bytes();
+                let mut req_buf = BufReader::new(&mut req_slice);
+                assert_eq!(
+                    expected,
+                    Request::try_from(&mut req_buf).map(|r| r.version),
+                    "{version}"
+                );
+            }
+        }
+
+        #[test]
+        fn requires_one_host() {
+            for req in [
+                b"GET / HTTP/1.1\r\n\r\n".as_slice(),
+                b"GET / HTTP/1.1\r\nHost: a\r\nHost: b\r\n\r\n",
+                b"GET / HTTP/1.0\r\nHost: a\r\nHost: a\r\n\r\n",
+            ] {
+                let mut req_slice = req;
+                let mut req_buf = BufReader::new(&mut req_slice);
+                assert_eq!(
+                    AppError::Client(ClientError::BadRequest),
+                    Request::try_from(&mut req_buf).unwrap_err()
+                );
+            }
+            // HTTP/1.0 didn't have it
+            let mut req_slice = b"GET / HTTP/1.0\r\n\r\n".as_slice();
+            let mut req_buf = BufReader::new(&mut req_slice);
+            assert!(Request::try_from(&mut req_buf).is_ok());
+        }
+
+        #[test]
+        fn handles_chunked_body() {
+            let req = b"POST /files/abc HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n3;ext\r\nabc\r\n0\r\nTrailer: x\r\n\r\n";
+            let mut req_slice = req.as_slice();
+            let mut req_buf = BufReader::new(&mut req_slice);
+            let req = Request::try_from(&mut req_buf).unwrap();
+            assert_eq!(b"abc".to_vec(), req.body);
+        }
+
+        #[test]
+        fn rejects_content_length_with_transfer_encoding() {
+            let req = b"POST /files/abc HTTP/1.1\r\nHost: localhost\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\n";
+            let mut req_slice = req.as_slice();
+            let mut req_buf = BufReader::new(&mut req_slice);
+            assert_eq!(
+                AppError::Client(ClientError::BadRequest),
+                Request::try_from(&mut req_buf).unwrap_err()
+            );
+        }
+
         #[test]
         fn handles_bad_request() {
             let req = b"/echo/abc\r\n\r\n";
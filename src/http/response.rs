@@ -1,38 +1,148 @@
-use super::{Encoding, Headers, MimeType, StatusCode};
-use crate::{constants::HTTP_VERSION, Result};
-use flate2::{write::GzEncoder, Compression};
-use std::io::Write;
+use super::{
+    chunked,
+    conditional::Validators,
+    encoding::{AcceptEncoding, Encoding},
+    header_map,
+    range::ContentRange,
+    Connection, HeaderMap, Headers, KeepAlive, Method, MimeType, StatusCode, Version,
+};
+use crate::{
+    constants::HTTP_VERSION,
+    errors::{ClientError, ServerError},
+    Result,
+};
+use httpdate::fmt_http_date;
+use std::{
+    fmt::{Debug, Formatter},
+    io::{copy, Cursor, Read, Write},
+};
+
+const MIN_COMPRESS_LEN: u64 = 64;
+
+// Written by the response from what it was built with, so a handler setting
+// one of these as well would send it twice
+const RESERVED_HEADERS: [Headers; 12] = [
+    Headers::ContentLength,
+    Headers::TransferEncoding,
+    Headers::Connection,
+    Headers::KeepAlive,
+    Headers::ContentType,
+    Headers::ContentEncoding,
+    Headers::Vary,
+    Headers::ETag,
+    Headers::LastModified,
+    Headers::Allow,
+    Headers::AcceptRanges,
+    Headers::ContentRange,
+];
+
+pub enum Body {
+    Bytes(Vec<u8>),
+    // Anything we can read from, e.g. a file, that is copied to the client as
+    // it is read rather than being loaded into memory first. Without a known
+    // length it goes out with chunked transfer coding.
+    Stream {
+        reader: Box<dyn Read>,
+        length: Option<u64>,
+    },
+}
+
+impl Debug for Body {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bytes(b) => f.debug_tuple("Bytes").field(b).finish(),
+            Self::Stream { length, .. } => {
+                f.debug_struct("Stream").field("length", length).finish()
+            }
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Response {
     status_code: StatusCode,
-    body: Option<Vec<u8>>,
+    body: Option<Body>,
     mime_type: Option<MimeType>,
-    encoding: Option<Vec<Encoding>>,
+    // The content coding applied to the body
+    encoding: Option<Encoding>,
+    vary: bool,
+    allow: Option<Vec<Method>>,
+    accept_ranges: bool,
+    content_range: Option<ContentRange>,
+    validators: Option<Validators>,
+    headers: HeaderMap,
+    connection: Option<Connection>,
+    keep_alive: Option<KeepAlive>,
+    omit_body: bool,
+    version: Version,
 }
 
 impl Response {
     pub fn builder() -> ResponseBuilder {
         ResponseBuilder::new()
     }
-    fn validate(&mut self) -> Result<()> {
-        if let Some(encoding_vec) = &self.encoding {
-            // Encoding -> Check for Gzip
-            if encoding_vec.contains(&Encoding::Gzip) {
-                let mut b = GzEncoder::new(Vec::new(), Compression::default());
-                let uncompressed = self.body.take();
-                let _ = b.write_all(&uncompressed.unwrap_or_default());
-                self.body = b.finish().ok();
-                Ok(())
-            } else {
-                self.encoding = None;
-                Ok(())
+    // https://www.rfc-editor.org/rfc/rfc9110#section-8.4 - compresses the body
+    // with the best coding the client accepts, if it's worth doing. The
+    // handler opts in by passing on the client's Accept-Encoding, and from
+    // then on the response varies with it whether or not this one is
+    // compressed.
+    fn encode(&mut self, accepted: Option<AcceptEncoding>) -> Result<()> {
+        let Some(accepted) = accepted else {
+            return Ok(());
+        };
+        // Errors and the like are sent as they are. A 304 is negotiated like
+        // the 200 it stands in for, see build.
+        let negotiated =
+            self.status_code.is_success() || self.status_code == StatusCode::NotModified;
+        if !negotiated || self.is_empty() {
+            return Ok(());
+        }
+        let compressible = self.compressible();
+        let encoding = accepted
+            .choose(compressible)
+            .ok_or(ClientError::NotAcceptable)?;
+        self.vary = compressible || encoding != Encoding::Identity;
+        if encoding == Encoding::Identity {
+            return Ok(());
+        }
+        self.body = match self.body.take() {
+            Some(Body::Stream { reader, .. }) => Some(Body::Stream {
+                // We can't know the compressed length up front
+                reader: encoding.encoder(reader)?,
+                length: None,
+            }),
+            Some(Body::Bytes(uncompressed)) => {
+                let mut compressed = Vec::new();
+                encoding
+                    .encoder(Box::new(Cursor::new(uncompressed)))?
+                    .read_to_end(&mut compressed)?;
+                Some(Body::Bytes(compressed))
             }
-        } else {
-            Ok(())
+            None => None,
+        };
+        self.encoding = Some(encoding);
+        // The compressed body isn't the same bytes as the file, so it can't
+        // have the same strong ETag
+        self.validators = self.validators.take().map(Validators::into_weak);
+        Ok(())
+    }
+
+    fn is_empty(&self) -> bool {
+        match &self.body {
+            Some(Body::Bytes(b)) => b.is_empty(),
+            Some(Body::Stream { length, .. }) => *length == Some(0),
+            None => true,
         }
     }
 
+    // Tiny bodies would only grow (gzip alone adds ~20 bytes), and
+    // compressing what is already compressed is just wasted effort
+    fn compressible(&self) -> bool {
+        self.length()
+            .map_or(true, |length| length >= MIN_COMPRESS_LEN)
+            && !self.mime_type.as_ref().is_some_and(MimeType::is_compressed)
+    }
+
     pub fn ok() -> Result<Response> {
         ResponseBuilder::new().build()
     }
@@ -41,52 +151,179 @@ impl Response {
             .status_code(StatusCode::NotFound)
             .build()
     }
-    pub fn created() -> Result<Response> {
+    pub fn no_content() -> Result<Response> {
         ResponseBuilder::new()
-            .status_code(StatusCode::Created)
+            .status_code(StatusCode::NoContent)
             .build()
     }
-    pub fn client_error() -> Result<Response> {
-        ResponseBuilder::new()
-            .status_code(StatusCode::ClientError)
-            .build()
+    pub fn status_code(&self) -> StatusCode {
+        self.status_code
     }
-    pub fn server_error() -> Result<Response> {
-        ResponseBuilder::new()
-            .status_code(StatusCode::ServerError)
-            .build()
+    // The router decides whether the connection outlives this response, so
+    // this is set after the handler has built it. None means we are closing.
+    pub fn set_connection(&mut self, keep_alive: Option<KeepAlive>) {
+        self.connection = Some(if keep_alive.is_some() {
+            Connection::KeepAlive
+        } else {
+            Connection::Close
+        });
+        self.keep_alive = keep_alive;
+    }
+    // For headers every response gets (Date, Server), unless the handler has
+    // already set its own
+    pub fn set_default_header(&mut self, name: impl AsRef<str>, value: impl Into<String>) {
+        if !self.headers.contains(&name) {
+            self.headers.append(name, value);
+        }
+    }
+    // For HEAD requests: the headers describe the body we would have sent,
+    // but it is never written
+    pub fn omit_body(&mut self) {
+        self.omit_body = true;
+    }
+    // The version of the client we're answering. HTTP/1.0 doesn't have
+    // chunked transfer coding, so a body of unknown length is sent as it is
+    // and ends when the connection does.
+    pub fn set_version(&mut self, version: Version) {
+        self.version = version;
+    }
+    // Whether the connection has to be closed to mark the end of the body
+    pub fn is_close_delimited(&self) -> bool {
+        self.version == Version::Http10
+            && self.status_code.allows_body()
+            && !self.omit_body
+            && self.length().is_none()
+    }
+    fn length(&self) -> Option<u64> {
+        match &self.body {
+            Some(Body::Bytes(b)) => Some(b.len() as u64),
+            Some(Body::Stream { length, .. }) => *length,
+            None => Some(0),
+        }
     }
+    fn head(&self) -> String {
+        // https://www.rfc-editor.org/rfc/rfc9110#section-2.5 - this is the
+        // version we speak, whichever HTTP/1 version the client does
+        let mut head = format!("{} {}\r\n", HTTP_VERSION, self.status_code);
+        let length = self.length();
+        // Always frame the body, otherwise the client has to wait for the
+        // connection to close to know the response is complete. There is no
+        // body to frame for 1xx, 204 and 304, and they mustn't say otherwise.
+        // https://www.rfc-editor.org/rfc/rfc9110#section-8.6
+        if self.status_code.allows_body() {
+            if length != Some(0) {
+                head.push_str(&format!(
+                    "{}: {}\r\n",
+                    Headers::ContentType,
+                    self.mime_type.as_ref().unwrap_or(&MimeType::Unknown)
+                ));
+            }
+            match length {
+                Some(length) => head.push_str(&format!("{}: {length}\r\n", Headers::ContentLength)),
+                None if self.version == Version::Http10 => {}
+                None => head.push_str(&format!("{}: chunked\r\n", Headers::TransferEncoding)),
+            }
+        }
+        // A 304 has no content to have been encoded, only the validators
+        // and Vary that say which encoding the client has
+        if let (Some(encoding), true) = (&self.encoding, self.status_code.allows_body()) {
+            head.push_str(&format!("{}: {encoding}\r\n", Headers::ContentEncoding));
+        }
+        if self.vary {
+            head.push_str(&format!(
+                "{}: {}\r\n",
+                Headers::Vary,
+                Headers::AcceptEncoding
+            ));
+        }
+        if self.accept_ranges {
+            head.push_str(&format!("{}: bytes\r\n", Headers::AcceptRanges));
+        }
+        if let Some(content_range) = &self.content_range {
+            head.push_str(&format!("{}: {content_range}\r\n", Headers::ContentRange));
+        }
+        if let Some(validators) = &self.validators {
+            head.push_str(&format!("{}: {}\r\n", Headers::ETag, validators.etag));
+            head.push_str(&format!(
+                "{}: {}\r\n",
+                Headers::LastModified,
+                fmt_http_date(validators.last_modified)
+            ));
+        }
+        if let Some(allow) = &self.allow {
+            let allow = allow
+                .iter()
+                .map(Method::to_string)
+                .collect::<Vec<String>>()
+                .join(", ");
+            head.push_str(&format!("{}: {allow}\r\n", Headers::Allow));
+        }
+        if let Some(connection) = &self.connection {
+            head.push_str(&format!("{}: {connection}\r\n", Headers::Connection));
+        }
+        if let Some(keep_alive) = &self.keep_alive {
+            head.push_str(&format!("{}: {keep_alive}\r\n", Headers::KeepAlive));
+        }
+        for (name, value) in self.headers.iter() {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        head.push_str("\r\n");
+        head
+    }
+    // A streamed body can only be read once, so it is not part of this, only
+    // its headers are. Use write_to to send it.
     pub fn as_bytes(&self) -> Vec<u8> {
-        let empty: Vec<u8> = Vec::new();
-        let content = self.body.as_ref().unwrap_or(&empty);
-        if !content.is_empty() {
-            let mut response = format!(
-                "{} {}\r\n{}: {content_type}\r\n{}: {content_length}\r\n\r\n",
-                HTTP_VERSION,
-                self.status_code,
-                Headers::ContentType,
-                Headers::ContentLength,
-                content_type = self.mime_type.as_ref().unwrap_or(&MimeType::Unknown),
-                content_length = content.len(),
-            )
-            .as_bytes()
-            .to_vec();
+        let mut response = self.head().into_bytes();
+        if let (Some(Body::Bytes(content)), false) = (&self.body, self.omit_body) {
             response.extend_from_slice(content);
-            response
-        } else {
-            format!("{} {}\r\n", HTTP_VERSION, self.status_code)
-                .as_bytes()
-                .to_vec()
         }
+        response
+    }
+    pub fn write_to<W: Write>(self, writer: &mut W) -> Result<()> {
+        writer.write_all(&self.as_bytes())?;
+        if self.omit_body {
+            writer.flush()?;
+            return Ok(());
+        }
+        match self.body {
+            Some(Body::Stream {
+                reader,
+                length: Some(length),
+            }) => {
+                let copied = copy(&mut reader.take(length), writer)?;
+                // If the source comes up short the client is still waiting
+                // on the rest, so the connection can't be reused
+                if copied != length {
+                    return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+                }
+            }
+            Some(Body::Stream {
+                mut reader,
+                length: None,
+            }) => match self.version {
+                Version::Http10 => {
+                    copy(&mut reader, writer)?;
+                }
+                Version::Http11 => chunked::encode(reader, writer)?,
+            },
+            _ => {}
+        }
+        writer.flush()?;
+        Ok(())
     }
 }
 
 #[derive(Debug, Default)]
 pub struct ResponseBuilder {
     status_code: Option<StatusCode>,
-    body: Option<Vec<u8>>,
+    body: Option<Body>,
     mime_type: Option<MimeType>,
-    encoding: Option<Vec<Encoding>>,
+    encoding: Option<AcceptEncoding>,
+    allow: Option<Vec<Method>>,
+    accept_ranges: bool,
+    content_range: Option<ContentRange>,
+    validators: Option<Validators>,
+    headers: HeaderMap,
 }
 
 impl ResponseBuilder {
@@ -103,28 +340,392 @@ impl ResponseBuilder {
         self
     }
     pub fn body(mut self, body: Option<Vec<u8>>) -> Self {
-        self.body = body;
+        self.body = body.map(Body::Bytes);
         self
     }
-    pub fn encoding(mut self, encoding: Option<&String>) -> Self {
-        if let Some(encoding_string) = encoding {
-            self.encoding = Some(
-                encoding_string
-                    .split(",")
-                    .map(Encoding::from)
-                    .collect::<Vec<Encoding>>(),
-            );
-        }
+    pub fn stream(mut self, reader: Box<dyn Read>, length: Option<u64>) -> Self {
+        self.body = Some(Body::Stream { reader, length });
+        self
+    }
+    pub fn allow(mut self, methods: &[Method]) -> Self {
+        self.allow = Some(methods.to_vec());
+        self
+    }
+    pub fn accept_ranges(mut self) -> Self {
+        self.accept_ranges = true;
+        self
+    }
+    pub fn content_range(mut self, content_range: ContentRange) -> Self {
+        self.content_range = Some(content_range);
+        self
+    }
+    pub fn validators(mut self, validators: Validators) -> Self {
+        self.validators = Some(validators);
+        self
+    }
+    // Any other header, e.g. Location or Cache-Control. Adding one more than
+    // once sends it more than once (Set-Cookie). The ones the builder has
+    // its own methods for, and the framing and connection headers, are
+    // refused by build (see RESERVED_HEADERS).
+    pub fn header(mut self, name: impl AsRef<str>, value: impl Into<String>) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+    // The client's Accept-Encoding, if any. Not calling this at all means the
+    // body is never compressed.
+    pub fn encoding(mut self, encoding: Option<&str>) -> Self {
+        self.encoding = Some(AcceptEncoding::parse(encoding));
         self
     }
     pub fn build(self) -> Result<Response> {
+        // A CR or LF in a value would let whoever chose it write their own
+        // headers, or a whole response. A second Content-Length or
+        // Transfer-Encoding would leave the client guessing where it ends,
+        // and a second Content-Type which one to believe.
+        let valid = self.headers.iter().all(|(name, value)| {
+            header_map::is_valid_name(name)
+                && header_map::is_valid_value(value.as_bytes())
+                && !RESERVED_HEADERS
+                    .iter()
+                    .any(|reserved| reserved.as_str().eq_ignore_ascii_case(name))
+        });
+        if !valid {
+            return Err(ServerError::Internal.into());
+        }
+        let status_code = self.status_code.unwrap_or(StatusCode::Ok);
         let mut response = Response {
-            status_code: self.status_code.unwrap_or(StatusCode::Ok),
+            status_code,
             body: self.body,
             mime_type: self.mime_type,
-            encoding: self.encoding,
+            encoding: None,
+            vary: false,
+            allow: self.allow,
+            accept_ranges: self.accept_ranges,
+            content_range: self.content_range,
+            validators: self.validators,
+            headers: self.headers,
+            connection: None,
+            keep_alive: None,
+            omit_body: false,
+            version: Version::Http11,
         };
-        response.validate()?;
+        // The body is there to negotiate the encoding for, but if the status
+        // code doesn't allow one there is nowhere to put it
+        response.encode(self.encoding)?;
+        if !status_code.allows_body() {
+            response.body = None;
+        }
         Ok(response)
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    mod response {
+        use crate::errors::{AppError, ServerError};
+        use crate::http::{
+            conditional::Validators, Headers, KeepAlive, MimeType, Response, StatusCode, Version,
+        };
+        use flate2::read::GzDecoder;
+        use std::io::Read;
+        use std::time::Duration;
+        use std::time::UNIX_EPOCH;
+
+        #[test]
+        fn frames_empty_body() {
+            let resp = Response::builder()
+                .status_code(StatusCode::Created)
+                .build()
+                .unwrap();
+            assert_eq!(
+                b"HTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\n".to_vec(),
+                resp.as_bytes()
+            );
+        }
+
+        #[test]
+        fn frames_responses_without_body() {
+            let resp = Response::builder()
+                .status_code(StatusCode::Continue)
+                .build()
+                .unwrap();
+            assert_eq!(b"HTTP/1.1 100 Continue\r\n\r\n".to_vec(), resp.as_bytes());
+
+            let mut resp = Response::no_content().unwrap();
+            resp.set_connection(None);
+            assert_eq!(
+                b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n".to_vec(),
+                resp.as_bytes()
+            );
+
+            let resp = Response::builder()
+                .status_code(StatusCode::NotModified)
+                .stream(Box::new(b"Hello!".as_slice()), Some(6))
+                .mime_type(MimeType::OctetStream)
+                .validators(Validators::new(3, UNIX_EPOCH))
+                .build()
+                .unwrap();
+            let mut stream = Vec::new();
+            resp.write_to(&mut stream).unwrap();
+            assert_eq!(
+                b"HTTP/1.1 304 Not Modified\r\nETag: \"3-0\"\r\nLast-Modified: Thu, 01 Jan 1970 00:00:00 GMT\r\n\r\n".to_vec(),
+                stream
+            );
+        }
+
+        #[test]
+        fn omits_chunked_body_but_keeps_framing() {
+            let mut resp = Response::builder()
+                .stream(Box::new(b"Hello!".as_slice()), None)
+                .mime_type(MimeType::OctetStream)
+                .build()
+                .unwrap();
+            resp.omit_body();
+            let mut stream = Vec::new();
+            resp.write_to(&mut stream).unwrap();
+            assert_eq!(
+                b"HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec(),
+                stream
+            );
+        }
+
+        #[test]
+        fn writes_keep_alive_headers() {
+            let mut resp = Response::ok().unwrap();
+            resp.set_connection(Some(KeepAlive {
+                timeout: Duration::from_secs(5),
+                max: 99,
+            }));
+            assert_eq!(
+                b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: keep-alive\r\nKeep-Alive: timeout=5, max=99\r\n\r\n".to_vec(),
+                resp.as_bytes()
+            );
+        }
+
+        #[test]
+        fn writes_connection_close() {
+            let mut resp = Response::ok().unwrap();
+            resp.set_connection(None);
+            assert_eq!(
+                b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_vec(),
+                resp.as_bytes()
+            );
+        }
+
+        #[test]
+        fn omits_body_but_keeps_length() {
+            let mut resp = Response::builder()
+                .body(Some(b"Hello!".to_vec()))
+                .mime_type(MimeType::PlainText)
+                .build()
+                .unwrap();
+            resp.omit_body();
+            let mut stream = Vec::new();
+            resp.write_to(&mut stream).unwrap();
+            assert_eq!(
+                b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 6\r\n\r\n"
+                    .to_vec(),
+                stream
+            );
+        }
+
+        #[test]
+        fn writes_extra_headers() {
+            let resp = Response::builder()
+                .status_code(StatusCode::Created)
+                .header("Location", "/files/abc")
+                .header("Set-Cookie", "a=1")
+                .header("Set-Cookie", "b=2")
+                .build()
+                .unwrap();
+            assert_eq!(
+                b"HTTP/1.1 201 Created\r\nContent-Length: 0\r\nLocation: /files/abc\r\nSet-Cookie: a=1\r\nSet-Cookie: b=2\r\n\r\n".to_vec(),
+                resp.as_bytes()
+            );
+        }
+
+        #[test]
+        fn keeps_headers_set_by_handler() {
+            let mut resp = Response::builder()
+                .header("server", "custom")
+                .build()
+                .unwrap();
+            resp.set_default_header(Headers::Server, "default");
+            resp.set_default_header(Headers::Date, "today");
+            assert_eq!(
+                b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nserver: custom\r\nDate: today\r\n\r\n"
+                    .to_vec(),
+                resp.as_bytes()
+            );
+        }
+
+        #[test]
+        fn rejects_invalid_headers() {
+            for (name, value) in [
+                ("X-Test", "a\r\nSet-Cookie: b=2"),
+                ("X Test", "a"),
+                ("", "a"),
+                ("Content-Length", "5"),
+                ("transfer-encoding", "chunked"),
+                ("Connection", "close"),
+                ("Keep-Alive", "timeout=1"),
+                ("content-type", "text/html"),
+                ("Content-Encoding", "gzip"),
+                ("Vary", "Accept-Encoding"),
+                ("ETag", "\"abc\""),
+                ("Last-Modified", "Thu, 01 Jan 1970 00:00:00 GMT"),
+                ("Allow", "GET"),
+                ("Accept-Ranges", "bytes"),
+                ("Content-Range", "bytes 0-1/2"),
+            ] {
+                assert_eq!(
+                    AppError::Server(ServerError::Internal),
+                    Response::builder().header(name, value).build().unwrap_err()
+                );
+            }
+        }
+
+        #[test]
+        fn compresses_body() {
+            let body = b"Hello, world! ".repeat(10);
+            let resp = Response::builder()
+                .body(Some(body.clone()))
+                .mime_type(MimeType::PlainText)
+                .validators(Validators::new(3, UNIX_EPOCH))
+                .encoding(Some("compress, gzip;q=0.5"))
+                .build()
+                .unwrap()
+                .as_bytes();
+            let resp = resp.as_slice();
+            let split = resp.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+            let head = String::from_utf8(resp[..split].to_vec()).unwrap();
+            assert!(head.contains("\r\nContent-Encoding: gzip\r\nVary: Accept-Encoding\r\n"));
+            assert!(head.contains("\r\nETag: W/\""));
+            let mut decoded = Vec::new();
+            GzDecoder::new(&resp[split..])
+                .read_to_end(&mut decoded)
+                .unwrap();
+            assert_eq!(body, decoded);
+        }
+
+        #[test]
+        fn varies_without_compressing() {
+            for accept_encoding in [Some("compress"), None] {
+                let resp = Response::builder()
+                    .body(Some(b"Hello, world! ".repeat(10)))
+                    .encoding(accept_encoding)
+                    .build()
+                    .unwrap();
+                let head = String::from_utf8(resp.as_bytes()).unwrap();
+                assert!(head.contains("\r\nContent-Length: 140\r\nVary: Accept-Encoding\r\n"));
+                assert!(!head.contains("Content-Encoding"));
+            }
+        }
+
+        #[test]
+        fn skips_compression() {
+            for (body, mime_type) in [
+                (Some(b"Hi!".to_vec()), MimeType::PlainText),
+                (None, MimeType::PlainText),
+                (Some(vec![0; 1024]), MimeType::from("image/png")),
+                (Some(vec![0; 1024]), MimeType::from("application/gzip")),
+            ] {
+                let resp = Response::builder()
+                    .body(body)
+                    .mime_type(mime_type)
+                    .encoding(Some("gzip"))
+                    .build()
+                    .unwrap();
+                let head = String::from_utf8_lossy(&resp.as_bytes()).into_owned();
+                assert!(!head.contains("Content-Encoding"));
+                assert!(!head.contains("Vary"));
+            }
+        }
+
+        #[test]
+        fn negotiates_not_modified_like_ok() {
+            let build = |status_code| {
+                Response::builder()
+                    .status_code(status_code)
+                    .stream(Box::new(std::io::empty()), Some(1024))
+                    .mime_type(MimeType::PlainText)
+                    .encoding(Some("gzip"))
+                    .validators(Validators::new(1024, UNIX_EPOCH))
+                    .build()
+                    .unwrap()
+            };
+            let ok = String::from_utf8(build(StatusCode::Ok).as_bytes()).unwrap();
+            let etag = "\r\nETag: W/\"400-0\"\r\n";
+            assert!(ok.contains(etag), "{ok}");
+            assert!(ok.contains("\r\nVary: Accept-Encoding\r\n"));
+            let mut stream = Vec::new();
+            build(StatusCode::NotModified)
+                .write_to(&mut stream)
+                .unwrap();
+            let not_modified = String::from_utf8(stream).unwrap();
+            assert!(not_modified.contains(etag), "{not_modified}");
+            assert!(not_modified.contains("\r\nVary: Accept-Encoding\r\n"));
+            // Nothing about content there isn't
+            assert!(!not_modified.contains("Content-"), "{not_modified}");
+            assert!(not_modified.ends_with("\r\n\r\n"));
+        }
+
+        #[test]
+        fn streams_body_with_known_length() {
+            let resp = Response::builder()
+                .stream(Box::new(b"Hello!".as_slice()), Some(6))
+                .mime_type(MimeType::OctetStream)
+                .build()
+                .unwrap();
+            let mut stream = Vec::new();
+            resp.write_to(&mut stream).unwrap();
+            assert_eq!(
+                b"HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nContent-Length: 6\r\n\r\nHello!".to_vec(),
+                stream
+            );
+        }
+
+        #[test]
+        fn streams_body_with_unknown_length_as_chunked() {
+            let resp = Response::builder()
+                .stream(Box::new(b"Hello!".as_slice()), None)
+                .mime_type(MimeType::OctetStream)
+                .build()
+                .unwrap();
+            let mut stream = Vec::new();
+            resp.write_to(&mut stream).unwrap();
+            assert_eq!(
+                b"HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nTransfer-Encoding: chunked\r\n\r\n6\r\nHello!\r\n0\r\n\r\n".to_vec(),
+                stream
+            );
+        }
+
+        #[test]
+        fn streams_body_with_unknown_length_to_http_1_0_until_close() {
+            let mut resp = Response::builder()
+                .stream(Box::new(b"Hello!".as_slice()), None)
+                .mime_type(MimeType::OctetStream)
+                .build()
+                .unwrap();
+            assert!(!resp.is_close_delimited());
+            resp.set_version(Version::Http10);
+            assert!(resp.is_close_delimited());
+            let mut stream = Vec::new();
+            resp.write_to(&mut stream).unwrap();
+            assert_eq!(
+                b"HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\n\r\nHello!".to_vec(),
+                stream
+            );
+        }
+
+        #[test]
+        fn fails_on_short_stream() {
+            let resp = Response::builder()
+                .stream(Box::new(b"Hello!".as_slice()), Some(10))
+                .build()
+                .unwrap();
+            assert!(resp.write_to(&mut Vec::new()).is_err());
+        }
+    }
+}
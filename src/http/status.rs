@@ -0,0 +1,216 @@
+use std::{
+    fmt::Display,
+    hash::{Hash, Hasher},
+};
+
+use crate::errors::{AppError, ServerError};
+
+// Each registered code with its canonical reason phrase, so the enum, the
+// numbers and the phrases can't get out of step
+macro_rules! status_codes {
+    ($($name:ident = $code:literal, $reason:literal;)+) => {
+        // https://www.iana.org/assignments/http-status-codes
+        // Anything that isn't registered is Custom, which has no reason phrase
+        #[derive(Debug, Clone, Copy)]
+        pub enum StatusCode {
+            $($name,)+
+            Custom(CustomCode),
+        }
+
+        impl StatusCode {
+            pub fn as_u16(&self) -> u16 {
+                match self {
+                    $(Self::$name => $code,)+
+                    Self::Custom(code) => code.0,
+                }
+            }
+
+            pub fn reason_phrase(&self) -> &'static str {
+                match self {
+                    $(Self::$name => $reason,)+
+                    Self::Custom(_) => "",
+                }
+            }
+
+            fn registered(code: u16) -> Option<Self> {
+                match code {
+                    $($code => Some(Self::$name),)+
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+// A code that isn't registered, which only TryFrom<u16> can make. That way
+// it's always three digits, and never a registered code under another name.
+#[derive(Debug, Clone, Copy)]
+pub struct CustomCode(u16);
+
+status_codes! {
+    Continue = 100, "Continue";
+    SwitchingProtocols = 101, "Switching Protocols";
+    Processing = 102, "Processing";
+    EarlyHints = 103, "Early Hints";
+    Ok = 200, "OK";
+    Created = 201, "Created";
+    Accepted = 202, "Accepted";
+    NonAuthoritativeInformation = 203, "Non-Authoritative Information";
+    NoContent = 204, "No Content";
+    ResetContent = 205, "Reset Content";
+    PartialContent = 206, "Partial Content";
+    MultiStatus = 207, "Multi-Status";
+    AlreadyReported = 208, "Already Reported";
+    ImUsed = 226, "IM Used";
+    MultipleChoices = 300, "Multiple Choices";
+    MovedPermanently = 301, "Moved Permanently";
+    Found = 302, "Found";
+    SeeOther = 303, "See Other";
+    NotModified = 304, "Not Modified";
+    UseProxy = 305, "Use Proxy";
+    TemporaryRedirect = 307, "Temporary Redirect";
+    PermanentRedirect = 308, "Permanent Redirect";
+    BadRequest = 400, "Bad Request";
+    Unauthorized = 401, "Unauthorized";
+    PaymentRequired = 402, "Payment Required";
+    Forbidden = 403, "Forbidden";
+    NotFound = 404, "Not Found";
+    MethodNotAllowed = 405, "Method Not Allowed";
+    NotAcceptable = 406, "Not Acceptable";
+    ProxyAuthenticationRequired = 407, "Proxy Authentication Required";
+    RequestTimeout = 408, "Request Timeout";
+    Conflict = 409, "Conflict";
+    Gone = 410, "Gone";
+    LengthRequired = 411, "Length Required";
+    PreconditionFailed = 412, "Precondition Failed";
+    ContentTooLarge = 413, "Content Too Large";
+    UriTooLong = 414, "URI Too Long";
+    UnsupportedMediaType = 415, "Unsupported Media Type";
+    RangeNotSatisfiable = 416, "Range Not Satisfiable";
+    ExpectationFailed = 417, "Expectation Failed";
+    MisdirectedRequest = 421, "Misdirected Request";
+    UnprocessableContent = 422, "Unprocessable Content";
+    Locked = 423, "Locked";
+    FailedDependency = 424, "Failed Dependency";
+    TooEarly = 425, "Too Early";
+    UpgradeRequired = 426, "Upgrade Required";
+    PreconditionRequired = 428, "Precondition Required";
+    TooManyRequests = 429, "Too Many Requests";
+    RequestHeaderFieldsTooLarge = 431, "Request Header Fields Too Large";
+    UnavailableForLegalReasons = 451, "Unavailable For Legal Reasons";
+    InternalServerError = 500, "Internal Server Error";
+    NotImplemented = 501, "Not Implemented";
+    BadGateway = 502, "Bad Gateway";
+    ServiceUnavailable = 503, "Service Unavailable";
+    GatewayTimeout = 504, "Gateway Timeout";
+    HttpVersionNotSupported = 505, "HTTP Version Not Supported";
+    VariantAlsoNegotiates = 506, "Variant Also Negotiates";
+    InsufficientStorage = 507, "Insufficient Storage";
+    LoopDetected = 508, "Loop Detected";
+    NotExtended = 510, "Not Extended";
+    NetworkAuthenticationRequired = 511, "Network Authentication Required";
+}
+
+impl StatusCode {
+    // https://www.rfc-editor.org/rfc/rfc9110#section-15
+    pub fn is_informational(&self) -> bool {
+        (100..200).contains(&self.as_u16())
+    }
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.as_u16())
+    }
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.as_u16())
+    }
+    pub fn is_server_error(&self) -> bool {
+        (500..600).contains(&self.as_u16())
+    }
+
+    // https://www.rfc-editor.org/rfc/rfc9110#section-6.4.1 - these never
+    // have content, even when the request wasn't HEAD
+    pub fn allows_body(&self) -> bool {
+        !(self.is_informational() || matches!(self.as_u16(), 204 | 304))
+    }
+}
+
+// Any three digit code can be sent. Registered ones come back as their
+// variant, so StatusCode::try_from(404) is StatusCode::NotFound.
+impl TryFrom<u16> for StatusCode {
+    type Error = AppError;
+    fn try_from(code: u16) -> Result<Self, Self::Error> {
+        match Self::registered(code) {
+            Some(status_code) => Ok(status_code),
+            None if (100..1000).contains(&code) => Ok(Self::Custom(CustomCode(code))),
+            None => Err(ServerError::Internal.into()),
+        }
+    }
+}
+
+// Compared by number, which is all a status code is
+impl PartialEq for StatusCode {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_u16() == other.as_u16()
+    }
+}
+
+impl Eq for StatusCode {}
+
+impl Hash for StatusCode {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_u16().hash(state);
+    }
+}
+
+// As it goes in the status line, e.g. "404 Not Found". The reason phrase
+// can be empty, but the space before it can't be left out.
+impl Display for StatusCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.as_u16(), self.reason_phrase())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod status {
+        use crate::http::StatusCode;
+
+        #[test]
+        fn has_numbers_and_reason_phrases() {
+            assert_eq!(404, StatusCode::NotFound.as_u16());
+            assert_eq!(
+                "Content Too Large",
+                StatusCode::ContentTooLarge.reason_phrase()
+            );
+            assert_eq!(
+                "429 Too Many Requests",
+                StatusCode::TooManyRequests.to_string()
+            );
+        }
+
+        #[test]
+        fn converts_from_numbers() {
+            assert_eq!(StatusCode::SeeOther, StatusCode::try_from(303).unwrap());
+            assert!(matches!(
+                StatusCode::try_from(303).unwrap(),
+                StatusCode::SeeOther
+            ));
+            assert_eq!("299 ", StatusCode::try_from(299).unwrap().to_string());
+            assert!(StatusCode::try_from(99).is_err());
+            assert!(StatusCode::try_from(1000).is_err());
+        }
+
+        #[test]
+        fn knows_its_class() {
+            assert!(StatusCode::EarlyHints.is_informational());
+            assert!(StatusCode::NoContent.is_success());
+            assert!(!StatusCode::PermanentRedirect.is_success());
+            assert!(StatusCode::try_from(499).unwrap().is_client_error());
+            assert!(StatusCode::ServiceUnavailable.is_server_error());
+            assert!(!StatusCode::Ok.is_client_error());
+            assert!(!StatusCode::SwitchingProtocols.allows_body());
+            assert!(!StatusCode::NotModified.allows_body());
+            assert!(StatusCode::NotFound.allows_body());
+        }
+    }
+}
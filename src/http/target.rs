@@ -0,0 +1,111 @@
+use crate::{errors::ClientError, Result};
+
+// https://www.rfc-editor.org/rfc/rfc3986#section-2.1
+// Every %XX is turned back into the byte it stands for. A % that isn't
+// followed by two hex digits, or bytes that don't make UTF-8, are a bad
+// request rather than something we guess at.
+pub fn percent_decode(s: &str) -> Result<String> {
+    let mut bytes = s.bytes();
+    let mut decoded = Vec::with_capacity(s.len());
+    while let Some(b) = bytes.next() {
+        if b != b'%' {
+            decoded.push(b);
+            continue;
+        }
+        let hex = [bytes.next(), bytes.next()];
+        let [Some(hi), Some(lo)] = hex.map(|d| d.and_then(|d| (d as char).to_digit(16))) else {
+            return Err(ClientError::BadRequest.into());
+        };
+        decoded.push((hi * 16 + lo) as u8);
+    }
+    String::from_utf8(decoded).map_err(|_| ClientError::BadRequest.into())
+}
+
+// The decoded segments of the path. Dot segments are resolved the way a
+// client would have (https://www.rfc-editor.org/rfc/rfc3986#section-5.2.4),
+// encoded dots included, so there is no way to climb out of the path with
+// "..". An encoded slash can't be told apart from a real one once decoded,
+// so it isn't allowed in a segment.
+pub fn path_segments(raw_path: &str) -> Result<Vec<String>> {
+    let mut segments: Vec<String> = Vec::new();
+    for segment in raw_path.split('/').filter(|s| !s.is_empty()) {
+        let segment = percent_decode(segment)?;
+        match segment.as_str() {
+            "." => {}
+            ".." => {
+                segments.pop();
+            }
+            s if s.contains(['/', '\0']) => return Err(ClientError::BadRequest.into()),
+            _ => segments.push(segment),
+        }
+    }
+    Ok(segments)
+}
+
+// https://url.spec.whatwg.org/#application/x-www-form-urlencoded
+// name=value pairs separated by &, in the order they were sent. A name can
+// come more than once, and one without = has an empty value. As in HTML
+// forms, + is a space.
+pub fn query_params(raw_query: &str) -> Result<Vec<(String, String)>> {
+    raw_query
+        .split('&')
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            let (name, value) = p.split_once('=').unwrap_or((p, ""));
+            let decode = |s: &str| percent_decode(&s.replace('+', " "));
+            Ok((decode(name)?, decode(value)?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod target {
+        use crate::http::target::{path_segments, percent_decode, query_params};
+
+        #[test]
+        fn decodes_percent_encoding() {
+            assert_eq!("my file.txt", percent_decode("my%20file.txt").unwrap());
+            assert_eq!("caf\u{e9}", percent_decode("caf%C3%a9").unwrap());
+            assert_eq!("100%", percent_decode("100%25").unwrap());
+            assert!(percent_decode("100%").is_err());
+            assert!(percent_decode("%2").is_err());
+            assert!(percent_decode("%zz").is_err());
+            // Not UTF-8
+            assert!(percent_decode("%ff").is_err());
+        }
+
+        #[test]
+        fn splits_paths() {
+            assert_eq!(
+                vec!["files", "my file.txt"],
+                path_segments("/files/my%20file.txt").unwrap()
+            );
+            assert_eq!(Vec::<String>::new(), path_segments("/").unwrap());
+            assert_eq!(
+                vec!["echo", "abc"],
+                path_segments("//echo/./x/../abc/").unwrap()
+            );
+            assert_eq!(
+                vec!["secret"],
+                path_segments("/files/%2e%2E/../secret").unwrap()
+            );
+            assert!(path_segments("/files/..%2Fsecret").is_err());
+            assert!(path_segments("/files/a%00").is_err());
+        }
+
+        #[test]
+        fn parses_queries() {
+            assert_eq!(
+                vec![
+                    ("a".to_owned(), "1".to_owned()),
+                    ("b".to_owned(), "".to_owned()),
+                    ("a".to_owned(), "x y&z".to_owned()),
+                ],
+                query_params("a=1&&b&a=x+y%26z").unwrap()
+            );
+            assert!(query_params("a=%").is_err());
+        }
+    }
+}
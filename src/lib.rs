@@ -10,6 +10,18 @@ pub(crate) mod constants {
     pub const TARGET_DIR: &str = "/tmp";
     pub const ADDRESS: &str = "127.0.0.1:4221";
     pub const HTTP_VERSION: &str = "HTTP/1.1";
+    pub const KEEP_ALIVE_TIMEOUT_SECS: u64 = 5;
+    pub const HEADER_TIMEOUT_SECS: u64 = 10;
+    pub const BODY_TIMEOUT_SECS: u64 = 10;
+    pub const WRITE_TIMEOUT_SECS: u64 = 10;
+    pub const MIN_DATA_RATE: u64 = 500;
+    pub const MAX_KEEP_ALIVE_REQUESTS: usize = 100;
+    pub const MAX_DECODED_BODY_SIZE: u64 = 64 * 1024 * 1024;
+    pub const MAX_REQUEST_LINE_LEN: usize = 8 * 1024;
+    pub const MAX_HEADER_SIZE: usize = 32 * 1024;
+    pub const MAX_HEADER_COUNT: usize = 100;
+    pub const MAX_BODY_SIZE: u64 = 64 * 1024 * 1024;
+    pub const SERVER_NAME: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
 }
 
 // Re-exports for main.rs
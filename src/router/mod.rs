@@ -0,0 +1,912 @@
+mod routes;
+
+use crate::{
+    constants::{MAX_DECODED_BODY_SIZE, SERVER_NAME},
+    dir::FileSystemAccess,
+    errors::AppError,
+    handlers::*,
+    http::{
+        ClientError, Connection, Headers, KeepAlive, Method, Request, RequestLimits, Response,
+        ServerError, StatusCode, Version,
+    },
+    server::{Phase, Timed},
+    Result,
+};
+use httpdate::fmt_http_date;
+use log::{error, info, warn};
+use routes::{Endpoint, Routes};
+use std::{
+    io::{BufRead, BufReader, ErrorKind, Read, Write},
+    time::SystemTime,
+};
+
+enum Operation<T> {
+    Endpoint(Endpoint<T>),
+    Options(Vec<Method>),
+    MethodNotAllowed(Vec<Method>),
+    NotFound,
+    Unsupported,
+    Unknown,
+}
+
+impl<T> Operation<T> {
+    // These are answered without looking at the body
+    fn rejects_body(&self) -> bool {
+        matches!(
+            self,
+            Self::MethodNotAllowed(_) | Self::NotFound | Self::Unsupported | Self::Unknown
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct Router<T>
+where
+    T: FileSystemAccess,
+{
+    dir: T,
+    send_date: bool,
+    send_server: bool,
+    decode_limit: u64,
+    limits: RequestLimits,
+    strict: bool,
+    routes: Routes<T>,
+}
+
+impl<T> Router<T>
+where
+    T: FileSystemAccess,
+{
+    pub fn new(dir: T) -> Self
+    where
+        T: FileSystemAccess,
+    {
+        Router {
+            dir,
+            send_date: true,
+            send_server: true,
+            decode_limit: MAX_DECODED_BODY_SIZE,
+            limits: RequestLimits::default(),
+            strict: false,
+            // HEAD is routed exactly like GET, the body is dropped on the way
+            // out. OPTIONS is answered for every route.
+            routes: Routes::new()
+                .add(&[Method::Get], "/", |req, _| {
+                    EmptyHandler::handle(HandlerArg::new(req))
+                })
+                .add(&[Method::Get], "/echo/{msg}", |req, _| {
+                    EchoHandler::handle(HandlerArg::new(req))
+                })
+                .add(&[Method::Get], "/user-agent", |req, _| {
+                    UserAgentHandler::handle(HandlerArg::new(req))
+                })
+                .add(
+                    &[Method::Get, Method::Post, Method::Put, Method::Delete],
+                    "/files/{name}",
+                    |req, dir| FileHandler::handle(FileHandlerArg::new(req, dir)),
+                ),
+        }
+    }
+
+    // Every response gets a Date and Server header unless these are turned off
+    pub fn send_date(mut self, send_date: bool) -> Self {
+        self.send_date = send_date;
+        self
+    }
+    pub fn send_server(mut self, send_server: bool) -> Self {
+        self.send_server = send_server;
+        self
+    }
+
+    // Compressed uploads are decompressed before they are stored, up to the
+    // given size. Larger ones get 413.
+    pub fn decode_limit(mut self, decode_limit: u64) -> Self {
+        self.decode_limit = decode_limit;
+        self
+    }
+
+    // Requests that go over these are answered with 413, 414 or 431 and the
+    // connection is closed
+    pub fn limits(mut self, limits: RequestLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    // Reject requests that are only ambiguous, such as a bare LF line ending
+    // or Content-Length sent twice, as well as those that are malformed.
+    // Either way it's a 400 and the connection is closed.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    fn write<W: Write>(&self, mut resp: Response, writer: &mut W) -> Result<()> {
+        // https://www.rfc-editor.org/rfc/rfc9110#section-6.6.1
+        if self.send_date {
+            resp.set_default_header(Headers::Date, fmt_http_date(SystemTime::now()));
+        }
+        if self.send_server {
+            resp.set_default_header(Headers::Server, SERVER_NAME);
+        }
+        resp.write_to(writer)
+    }
+
+    // For when we can't trust where the next request would start, so we
+    // answer with the error and close
+    fn reject<W: Write>(&self, writer: &mut W, e: AppError) -> Result<Connection> {
+        let mut resp = ErrorHandler::handle(ErrorHandlerArg::new(e))?;
+        resp.set_connection(None);
+        self.write(resp, writer)?;
+        Ok(Connection::Close)
+    }
+
+    // Finds what the request is for, and fills in the parameters of the
+    // route it matched
+    fn resolve(&self, req: &mut Request) -> Operation<T> {
+        match req.method {
+            Method::Unsupported => return Operation::Unsupported,
+            Method::Unknown => return Operation::Unknown,
+            _ => {}
+        }
+        let Some((route, params)) = self.routes.find(&req.path_parts) else {
+            return Operation::NotFound;
+        };
+        match (req.method, route.endpoint(req.method)) {
+            (_, Some(endpoint)) => {
+                req.params = params;
+                Operation::Endpoint(endpoint)
+            }
+            (Method::Options, None) => Operation::Options(route.methods()),
+            (_, None) => Operation::MethodNotAllowed(route.methods()),
+        }
+    }
+
+    fn dispatch(&self, operation: Operation<T>, req: &Request) -> Result<Response> {
+        match operation {
+            Operation::Endpoint(endpoint) => endpoint(req, &self.dir),
+            Operation::Options(allow) => OptionsHandler::handle(AllowHandlerArg::new(&allow)),
+            Operation::MethodNotAllowed(allow) => {
+                MethodNotAllowedHandler::handle(AllowHandlerArg::new(&allow))
+            }
+            Operation::NotFound => NotFoundHandler::handle(HandlerArg::new(req)),
+            Operation::Unsupported => {
+                ErrorHandler::handle(ErrorHandlerArg::new(ServerError::NotImplemented.into()))
+            }
+            Operation::Unknown => {
+                ErrorHandler::handle(ErrorHandlerArg::new(ClientError::BadRequest.into()))
+            }
+        }
+        .or_else(|e| ErrorHandler::handle(ErrorHandlerArg::new(e)))
+    }
+
+    // Handles a single request off the connection. The reader lives as long
+    // as the connection does, so pipelined requests that were buffered along
+    // with this one are still there for the next call. The caller passes the
+    // keep-alive parameters it is willing to offer (None if this must be the
+    // last request), and gets back whether the connection should stay open.
+    pub fn route<R, W>(
+        &self,
+        reader: &mut BufReader<R>,
+        writer: &mut W,
+        keep_alive: Option<KeepAlive>,
+    ) -> Result<Connection>
+    where
+        R: Read + Timed,
+        W: Write,
+    {
+        // Waiting for the next request on a persistent connection: the client
+        // hanging up or the idle timeout firing are both a normal close
+        reader.get_mut().start(Phase::Idle);
+        match reader.fill_buf() {
+            Ok([]) => return Ok(Connection::Close),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Ok(Connection::Close)
+            }
+            Err(e) => return Err(e.into()),
+            Ok(_) => {}
+        }
+
+        // Once a request has started, running out of time on it is a 408
+        reader.get_mut().start(Phase::Head);
+        let mut req = match Request::read_head(reader, &self.limits, self.strict) {
+            Ok(req) => req,
+            Err(e) => return self.reject(writer, e),
+        };
+        let operation = self.resolve(&mut req);
+
+        // https://www.rfc-editor.org/rfc/rfc9110#section-10.1.1 - the client is
+        // waiting to hear whether it should send the body. If we already know
+        // the answer is no, say so now, and close rather than read a body
+        // that may or may not be on its way. HTTP/1.0 clients can't have
+        // meant it, so for them it's ignored.
+        let expect = match req.version {
+            Version::Http11 => req.get_header(Headers::Expect),
+            Version::Http10 => None,
+        };
+        if let Some(expect) = expect {
+            if !expect.eq_ignore_ascii_case("100-continue") {
+                return self.reject(writer, ClientError::ExpectationFailed.into());
+            }
+            if operation.rejects_body() {
+                let mut resp = self.dispatch(operation, &req)?;
+                resp.set_connection(None);
+                self.write(resp, writer)?;
+                return Ok(Connection::Close);
+            }
+            // Interim, so none of the headers of the final response
+            Response::builder()
+                .status_code(StatusCode::Continue)
+                .build()?
+                .write_to(writer)?;
+        }
+
+        reader.get_mut().start(Phase::Body);
+        if let Err(e) = req.read_body(reader, &self.limits, self.strict) {
+            return self.reject(writer, e);
+        }
+
+        // The body has been read in full, so the connection is still fine if
+        // it can't be decoded
+        // Uploads are the bodies a handler keeps
+        let uploaded = matches!(operation, Operation::Endpoint(_))
+            && matches!(req.method, Method::Post | Method::Put);
+        let decoded = if uploaded {
+            req.decode_body(self.decode_limit)
+        } else {
+            Ok(())
+        };
+        let mut resp = match decoded {
+            Ok(()) => self.dispatch(operation, &req)?,
+            Err(e) => ErrorHandler::handle(ErrorHandlerArg::new(e))?,
+        };
+
+        if req.method == Method::Head {
+            resp.omit_body();
+        }
+        resp.set_version(req.version);
+
+        // Errors on our side need looking into, the client's less so
+        let status = resp.status_code();
+        if status.is_server_error() {
+            error!("{} {} -> {status}", req.method, req.target());
+        } else if status.is_client_error() {
+            warn!("{} {} -> {status}", req.method, req.target());
+        } else {
+            info!("{} {} -> {status}", req.method, req.target());
+        }
+
+        let connection = match (req.connection(), keep_alive) {
+            (Connection::KeepAlive, Some(k)) if !resp.is_close_delimited() => {
+                resp.set_connection(Some(k));
+                Connection::KeepAlive
+            }
+            _ => {
+                resp.set_connection(None);
+                Connection::Close
+            }
+        };
+        self.write(resp, writer)?;
+        Ok(connection)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod router {
+        use crate::dir::{FileMetadata, FileSystemAccess};
+        use crate::http::{Connection, KeepAlive, RequestLimits};
+        use crate::router::Router;
+        use crate::server::{Phase, Timed};
+        use crate::Result;
+        use flate2::{write::GzEncoder, Compression};
+        use std::{
+            cell::RefCell,
+            io::{BufReader, ErrorKind, Read, Write},
+            rc::Rc,
+            time::{Duration, UNIX_EPOCH},
+        };
+
+        struct MockDir;
+
+        // Requests are read from byte slices, which never time out
+        impl Timed for &[u8] {
+            fn start(&mut self, _phase: Phase) {}
+        }
+
+        // Sends the start of a request and then nothing
+        struct Stalled(&'static [u8]);
+
+        impl Read for Stalled {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                match self.0.read(buf)? {
+                    0 => Err(ErrorKind::TimedOut.into()),
+                    read => Ok(read),
+                }
+            }
+        }
+
+        impl Timed for Stalled {
+            fn start(&mut self, _phase: Phase) {}
+        }
+
+        impl FileSystemAccess for MockDir {
+            fn try_open(&self, _src: &str) -> Result<Box<dyn std::io::Read>> {
+                Ok(Box::new(b"Hi!".as_slice()))
+            }
+            fn try_metadata(&self, _src: &str) -> Result<FileMetadata> {
+                Ok(FileMetadata {
+                    len: 3,
+                    modified: UNIX_EPOCH,
+                })
+            }
+            fn try_read_range(
+                &self,
+                _src: &str,
+                range: &std::ops::Range<u64>,
+            ) -> Result<Box<dyn std::io::Read>> {
+                let range = range.start as usize..range.end as usize;
+                Ok(Box::new(&b"Hi!"[range]))
+            }
+            fn try_write(&self, _src: &str, _d: &[u8]) -> Result<()> {
+                Ok(())
+            }
+            fn try_delete(&self, _src: &str) -> Result<()> {
+                Ok(())
+            }
+            fn check_file_exists(&self, _src: &str) -> bool {
+                true
+            }
+            fn try_create(&self) -> Result<()> {
+                Ok(())
+            }
+            fn check_dir_exists(&self) -> bool {
+                true
+            }
+        }
+
+        // Keeps what was written, for tests that look at what the handler got
+        #[derive(Default)]
+        struct Uploads(Rc<RefCell<Vec<u8>>>);
+
+        impl FileSystemAccess for Uploads {
+            fn try_open(&self, src: &str) -> Result<Box<dyn std::io::Read>> {
+                MockDir.try_open(src)
+            }
+            fn try_metadata(&self, src: &str) -> Result<FileMetadata> {
+                MockDir.try_metadata(src)
+            }
+            fn try_read_range(
+                &self,
+                src: &str,
+                range: &std::ops::Range<u64>,
+            ) -> Result<Box<dyn std::io::Read>> {
+                MockDir.try_read_range(src, range)
+            }
+            fn try_write(&self, _src: &str, d: &[u8]) -> Result<()> {
+                *self.0.borrow_mut() = d.to_vec();
+                Ok(())
+            }
+            fn try_delete(&self, src: &str) -> Result<()> {
+                MockDir.try_delete(src)
+            }
+            fn check_file_exists(&self, src: &str) -> bool {
+                MockDir.check_file_exists(src)
+            }
+            fn try_create(&self) -> Result<()> {
+                MockDir.try_create()
+            }
+            fn check_dir_exists(&self) -> bool {
+                MockDir.check_dir_exists()
+            }
+        }
+
+        const KEEP_ALIVE: Option<KeepAlive> = Some(KeepAlive {
+            timeout: Duration::from_secs(5),
+            max: 10,
+        });
+
+        #[test]
+        fn handles_pipelined_requests_in_order() {
+            let req = b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nContent-Length: 3\r\n\r\nHi!GET /echo/one HTTP/1.1\r\nHost: localhost\r\n\r\nGET /echo/two HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+            let router = Router::new(MockDir);
+            let mut reader = BufReader::new(req.as_slice());
+            let mut writer = Vec::new();
+            assert_eq!(
+                Connection::KeepAlive,
+                router.route(&mut reader, &mut writer, KEEP_ALIVE).unwrap()
+            );
+            assert_eq!(
+                Connection::KeepAlive,
+                router.route(&mut reader, &mut writer, KEEP_ALIVE).unwrap()
+            );
+            assert_eq!(
+                Connection::Close,
+                router.route(&mut reader, &mut writer, KEEP_ALIVE).unwrap()
+            );
+            let written = String::from_utf8(writer).unwrap();
+            let created = written.find("201 Created").unwrap();
+            let one = written.find("\r\n\r\none").unwrap();
+            let two = written.find("\r\n\r\ntwo").unwrap();
+            assert!(created < one && one < two);
+        }
+
+        #[test]
+        fn handles_head_like_get_without_body() {
+            let router = Router::new(MockDir);
+            let mut get = Vec::new();
+            let mut head = Vec::new();
+            for (method, writer) in [("GET", &mut get), ("HEAD", &mut head)] {
+                let req = format!("{method} /files/a HTTP/1.1\r\nHost: localhost\r\n\r\n");
+                let mut reader = BufReader::new(req.as_bytes());
+                router.route(&mut reader, writer, KEEP_ALIVE).unwrap();
+            }
+            assert!(get.ends_with(b"\r\n\r\nHi!"));
+            let head = String::from_utf8(head).unwrap();
+            assert!(head.contains("\r\nContent-Length: 3\r\n"));
+            assert!(head.ends_with("\r\n\r\n"));
+            assert_eq!(&get[..get.len() - 3], head.as_bytes());
+        }
+
+        #[test]
+        fn handles_method_not_allowed() {
+            let router = Router::new(MockDir);
+            let mut reader =
+                BufReader::new(b"POST /echo/abc HTTP/1.1\r\nHost: localhost\r\n\r\n".as_slice());
+            let mut writer = Vec::new();
+            router.route(&mut reader, &mut writer, KEEP_ALIVE).unwrap();
+            let written = String::from_utf8(writer).unwrap();
+            assert!(written.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+            assert!(written.contains("\r\nAllow: GET, HEAD, OPTIONS\r\n"));
+        }
+
+        #[test]
+        fn handles_options() {
+            let router = Router::new(MockDir);
+            let mut reader = BufReader::new(
+                b"OPTIONS /files/abc HTTP/1.1\r\nHost: localhost\r\n\r\n".as_slice(),
+            );
+            let mut writer = Vec::new();
+            router.route(&mut reader, &mut writer, KEEP_ALIVE).unwrap();
+            let written = String::from_utf8(writer).unwrap();
+            assert!(written.starts_with("HTTP/1.1 204 No Content\r\n"));
+            assert!(written.contains("\r\nAllow: GET, HEAD, POST, PUT, DELETE, OPTIONS\r\n"));
+        }
+
+        #[test]
+        fn routes_by_pattern() {
+            let router = Router::new(MockDir);
+            for (req, status, body) in [
+                ("GET /echo/a%20b", "200 OK", "a b"),
+                ("GET /echo", "404 Not Found", ""),
+                ("GET /echo/a/b", "404 Not Found", ""),
+                ("GET /files", "404 Not Found", ""),
+                ("GET /files/a", "200 OK", "Hi!"),
+                ("DELETE /echo/a", "405 Method Not Allowed", ""),
+            ] {
+                let req = format!("{req} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+                let mut reader = BufReader::new(req.as_bytes());
+                let mut writer = Vec::new();
+                router.route(&mut reader, &mut writer, KEEP_ALIVE).unwrap();
+                let written = String::from_utf8(writer).unwrap();
+                assert!(
+                    written.starts_with(&format!("HTTP/1.1 {status}\r\n")),
+                    "{req}"
+                );
+                assert!(written.ends_with(&format!("\r\n\r\n{body}")), "{req}");
+            }
+        }
+
+        #[test]
+        fn continues_expected_body() {
+            let router = Router::new(MockDir);
+            let mut reader = BufReader::new(
+                b"PUT /files/a HTTP/1.1\r\nHost: localhost\r\nExpect: 100-continue\r\nContent-Length: 3\r\n\r\nHi!"
+                    .as_slice(),
+            );
+            let mut writer = Vec::new();
+            router.route(&mut reader, &mut writer, KEEP_ALIVE).unwrap();
+            let written = String::from_utf8(writer).unwrap();
+            assert!(written.starts_with("HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 204 No Content\r\n"));
+        }
+
+        #[test]
+        fn answers_http_1_0() {
+            let router = Router::new(MockDir);
+            // Closed unless the client asks to keep it open
+            let mut reader = BufReader::new(b"GET /echo/a HTTP/1.0\r\n\r\n".as_slice());
+            let mut writer = Vec::new();
+            assert_eq!(
+                Connection::Close,
+                router.route(&mut reader, &mut writer, KEEP_ALIVE).unwrap()
+            );
+            let written = String::from_utf8(writer).unwrap();
+            assert!(written.starts_with("HTTP/1.1 200 OK\r\n"));
+            assert!(written.contains("\r\nConnection: close\r\n"));
+            let mut reader = BufReader::new(
+                b"GET /echo/a HTTP/1.0\r\nConnection: keep-alive\r\n\r\n".as_slice(),
+            );
+            let mut writer = Vec::new();
+            assert_eq!(
+                Connection::KeepAlive,
+                router.route(&mut reader, &mut writer, KEEP_ALIVE).unwrap()
+            );
+            // Expect wasn't in HTTP/1.0, so there is nothing to answer
+            let mut reader = BufReader::new(
+                b"PUT /files/a HTTP/1.0\r\nExpect: 100-continue\r\nContent-Length: 3\r\n\r\nHi!"
+                    .as_slice(),
+            );
+            let mut writer = Vec::new();
+            router.route(&mut reader, &mut writer, KEEP_ALIVE).unwrap();
+            let written = String::from_utf8(writer).unwrap();
+            assert!(written.starts_with("HTTP/1.1 204 No Content\r\n"));
+        }
+
+        #[test]
+        fn rejects_unsupported_versions() {
+            let router = Router::new(MockDir);
+            let mut reader =
+                BufReader::new(b"GET / HTTP/2.0\r\nHost: localhost\r\n\r\n".as_slice());
+            let mut writer = Vec::new();
+            assert_eq!(
+                Connection::Close,
+                router.route(&mut reader, &mut writer, KEEP_ALIVE).unwrap()
+            );
+            assert!(writer.starts_with(b"HTTP/1.1 505 HTTP Version Not Supported\r\n"));
+        }
+
+        #[test]
+        fn rejects_expected_body_early() {
+            let router = Router::new(MockDir);
+            for (req, status) in [
+                (
+                    "POST /nope HTTP/1.1\r\nHost: localhost\r\nExpect: 100-continue\r\nContent-Length: 3\r\n\r\n",
+                    "404 Not Found",
+                ),
+                (
+                    "POST /files/a HTTP/1.1\r\nHost: localhost\r\nExpect: something\r\nContent-Length: 3\r\n\r\n",
+                    "417 Expectation Failed",
+                ),
+            ] {
+                let mut reader = BufReader::new(req.as_bytes());
+                let mut writer = Vec::new();
+                assert_eq!(
+                    Connection::Close,
+                    router.route(&mut reader, &mut writer, KEEP_ALIVE).unwrap()
+                );
+                let written = String::from_utf8(writer).unwrap();
+                assert!(written.starts_with(&format!("HTTP/1.1 {status}\r\n")));
+            }
+        }
+
+        #[test]
+        fn sends_date_and_server() {
+            for (send_date, send_server) in [(true, true), (false, false)] {
+                let router = Router::new(MockDir)
+                    .send_date(send_date)
+                    .send_server(send_server);
+                let mut reader =
+                    BufReader::new(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n".as_slice());
+                let mut writer = Vec::new();
+                router.route(&mut reader, &mut writer, KEEP_ALIVE).unwrap();
+                let written = String::from_utf8(writer).unwrap();
+                assert_eq!(send_date, written.contains("\r\nDate: "));
+                assert_eq!(
+                    send_server,
+                    written.contains("\r\nServer: http-server-rust/")
+                );
+            }
+        }
+
+        #[test]
+        fn rejects_unknown_content_coding() {
+            let router = Router::new(MockDir);
+            let mut reader = BufReader::new(
+                b"PUT /files/a HTTP/1.1\r\nHost: localhost\r\nContent-Encoding: compress\r\nContent-Length: 3\r\n\r\nHi!GET / HTTP/1.1\r\nHost: localhost\r\n\r\n"
+                    .as_slice(),
+            );
+            let mut writer = Vec::new();
+            assert_eq!(
+                Connection::KeepAlive,
+                router.route(&mut reader, &mut writer, KEEP_ALIVE).unwrap()
+            );
+            let written = String::from_utf8(writer).unwrap();
+            assert!(written.starts_with("HTTP/1.1 415 Unsupported Media Type\r\n"));
+            assert!(written.contains("\r\nAccept-Encoding: "));
+            // The body was read, so the next request is where it should be
+            let mut writer = Vec::new();
+            router.route(&mut reader, &mut writer, KEEP_ALIVE).unwrap();
+            assert!(writer.starts_with(b"HTTP/1.1 200 OK\r\n"));
+        }
+
+        #[test]
+        fn stores_decoded_bodies() {
+            let uploads = Uploads::default();
+            let stored = Rc::clone(&uploads.0);
+            let router = Router::new(uploads);
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(b"Hi!").unwrap();
+            let body = encoder.finish().unwrap();
+            let mut req = format!(
+                "PUT /files/a HTTP/1.1\r\nHost: localhost\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+                body.len()
+            )
+            .into_bytes();
+            req.extend(body);
+            let mut reader = BufReader::new(req.as_slice());
+            let mut writer = Vec::new();
+            router.route(&mut reader, &mut writer, KEEP_ALIVE).unwrap();
+            assert!(writer.starts_with(b"HTTP/1.1 204 No Content\r\n"));
+            assert_eq!(b"Hi!".to_vec(), *stored.borrow());
+        }
+
+        #[test]
+        fn rejects_requests_over_limits() {
+            let router = Router::new(MockDir).limits(RequestLimits {
+                request_line: 32,
+                header_size: 64,
+                header_count: 3,
+                body: 8,
+            });
+            for (req, status) in [
+                (
+                    "GET /echo/aaaaaaaaaaaaaaaaaaaaaaaaaaaa HTTP/1.1\r\nHost: localhost\r\n\r\n".to_owned(),
+                    "414 URI Too Long",
+                ),
+                (
+                    format!("GET / HTTP/1.1\r\nHost: localhost\r\nX-Long: {}\r\n\r\n", "a".repeat(64)),
+                    "431 Request Header Fields Too Large",
+                ),
+                (
+                    "GET / HTTP/1.1\r\nHost: localhost\r\nA: 1\r\nB: 2\r\nC: 3\r\nD: 4\r\n\r\n".to_owned(),
+                    "431 Request Header Fields Too Large",
+                ),
+                (
+                    "POST /files/a HTTP/1.1\r\nHost: localhost\r\nContent-Length: 9\r\n\r\n123456789".to_owned(),
+                    "413 Content Too Large",
+                ),
+                (
+                    "POST /files/a HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n9\r\n123456789\r\n0\r\n\r\n"
+                        .to_owned(),
+                    "413 Content Too Large",
+                ),
+            ] {
+                let mut reader = BufReader::new(req.as_bytes());
+                let mut writer = Vec::new();
+                assert_eq!(
+                    Connection::Close,
+                    router.route(&mut reader, &mut writer, KEEP_ALIVE).unwrap()
+                );
+                let written = String::from_utf8(writer).unwrap();
+                assert!(written.starts_with(&format!("HTTP/1.1 {status}\r\n")), "{written}");
+            }
+            // Right at the limits is fine
+            let mut reader = BufReader::new(
+                b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nA: 1\r\nContent-Length: 8\r\n\r\n12345678".as_slice(),
+            );
+            let mut writer = Vec::new();
+            router.route(&mut reader, &mut writer, KEEP_ALIVE).unwrap();
+            assert!(writer.starts_with(b"HTTP/1.1 201 Created\r\n"));
+        }
+
+        #[test]
+        fn times_out_stalled_requests() {
+            let router = Router::new(MockDir);
+            for req in [
+                b"GET / HTTP/1.1\r\nHost: loc".as_slice(),
+                b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nContent-Length: 9\r\n\r\nHi!",
+            ] {
+                let mut reader = BufReader::new(Stalled(req));
+                let mut writer = Vec::new();
+                assert_eq!(
+                    Connection::Close,
+                    router.route(&mut reader, &mut writer, KEEP_ALIVE).unwrap()
+                );
+                assert!(writer.starts_with(b"HTTP/1.1 408 Request Timeout\r\n"));
+            }
+            // Between requests it's a quiet close
+            let mut reader = BufReader::new(Stalled(b""));
+            let mut writer = Vec::new();
+            assert_eq!(
+                Connection::Close,
+                router.route(&mut reader, &mut writer, KEEP_ALIVE).unwrap()
+            );
+            assert!(writer.is_empty());
+        }
+
+        #[test]
+        fn closes_at_end_of_stream() {
+            let router = Router::new(MockDir);
+            let mut reader = BufReader::new(b"".as_slice());
+            let mut writer = Vec::new();
+            assert_eq!(
+                Connection::Close,
+                router.route(&mut reader, &mut writer, KEEP_ALIVE).unwrap()
+            );
+            assert!(writer.is_empty());
+        }
+
+        // Requests that two servers in a row could split up differently, so
+        // that part of one is taken as the start of another. Strictly, each
+        // one gets a 400 and the connection is closed, so nothing after it
+        // is ever read.
+        mod smuggling {
+            use super::{MockDir, KEEP_ALIVE};
+            use crate::http::Connection;
+            use crate::router::Router;
+            use std::io::BufReader;
+
+            fn route(req: &[u8], strict: bool) -> (Connection, String) {
+                let router = Router::new(MockDir).strict(strict);
+                let mut reader = BufReader::new(req);
+                let mut writer = Vec::new();
+                let connection = router.route(&mut reader, &mut writer, KEEP_ALIVE).unwrap();
+                (connection, String::from_utf8(writer).unwrap())
+            }
+
+            fn assert_rejected(req: &[u8], strict: bool) {
+                let (connection, written) = route(req, strict);
+                assert_eq!(Connection::Close, connection, "{req:?}");
+                assert!(
+                    written.starts_with("HTTP/1.1 400 Bad Request\r\n"),
+                    "{req:?}: {written}"
+                );
+                // Only the one response, nothing smuggled in after it
+                assert_eq!(1, written.matches("HTTP/1.1 ").count(), "{req:?}");
+            }
+
+            fn assert_accepted(req: &[u8], strict: bool) {
+                let (_, written) = route(req, strict);
+                assert!(!written.starts_with("HTTP/1.1 400 "), "{req:?}: {written}");
+            }
+
+            #[test]
+            fn rejects_conflicting_content_lengths() {
+                for req in [
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nContent-Length: 3\r\nContent-Length: 27\r\n\r\nHi!GET /echo/smuggled HTTP/1.1\r\nHost: localhost\r\n\r\n".as_slice(),
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nContent-Length: 3, 27\r\n\r\nHi!GET /echo/smuggled HTTP/1.1\r\nHost: localhost\r\n\r\n",
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nContent-Length: +3\r\n\r\nHi!",
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nContent-Length: -3\r\n\r\nHi!",
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0x3\r\n\r\nHi!",
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nContent-Length: 3 3\r\n\r\nHi!",
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nContent-Length:\r\n\r\nHi!",
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nContent-Length: 99999999999999999999\r\n\r\nHi!",
+                ] {
+                    assert_rejected(req, true);
+                    // These are never safe to read
+                    assert_rejected(req, false);
+                }
+                // The same length twice is only fine when we're not strict
+                let req =
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nContent-Length: 3\r\ncontent-length: 3\r\n\r\nHi!";
+                assert_rejected(req, true);
+                assert_accepted(req, false);
+            }
+
+            #[test]
+            fn rejects_content_length_with_transfer_encoding() {
+                for req in [
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nContent-Length: 4\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n".as_slice(),
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\nContent-Length: 4\r\n\r\n0\r\n\r\n",
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\ncontent-length: 4\r\ntransfer-encoding: CHUNKED\r\n\r\n0\r\n\r\n",
+                ] {
+                    assert_rejected(req, true);
+                    assert_rejected(req, false);
+                }
+            }
+
+            #[test]
+            fn rejects_obfuscated_transfer_encoding() {
+                for req in [
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: xchunked\r\n\r\n0\r\n\r\n".as_slice(),
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked, identity\r\n\r\n0\r\n\r\n",
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\x0b\r\n\r\n0\r\n\r\n",
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding : chunked\r\n\r\n0\r\n\r\n",
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nContent-Length : 3\r\n\r\nHi!",
+                ] {
+                    assert_rejected(req, true);
+                    assert_rejected(req, false);
+                }
+                // Folded onto the next line
+                let req =
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding:\r\n chunked\r\n\r\n0\r\n\r\n";
+                assert_rejected(req, true);
+                assert_accepted(req, false);
+            }
+
+            #[test]
+            fn rejects_bare_line_feeds() {
+                for req in [
+                    b"GET / HTTP/1.1\nHost: localhost\r\n\r\n".as_slice(),
+                    b"GET / HTTP/1.1\r\nHost: localhost\n\r\n",
+                    b"GET / HTTP/1.1\r\nHost: localhost\r\n\n",
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n3\nHi!\r\n0\r\n\r\n",
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nHi!\n0\r\n\r\n",
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nHi!\r\n0\r\n\n",
+                ] {
+                    assert_rejected(req, true);
+                    assert_accepted(req, false);
+                }
+            }
+
+            #[test]
+            fn rejects_malformed_chunks() {
+                for req in [
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n+3\r\nHi!\r\n0\r\n\r\n".as_slice(),
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n0x3\r\nHi!\r\n0\r\n\r\n",
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nHi!X\r\n0\r\n\r\n",
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\nfffffffffffffffff\r\nHi!\r\n0\r\n\r\n",
+                ] {
+                    assert_rejected(req, true);
+                    assert_rejected(req, false);
+                }
+                let req = b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n3 ;ext\r\nHi!\r\n0\r\n\r\n";
+                assert_rejected(req, true);
+                assert_accepted(req, false);
+            }
+
+            #[test]
+            fn rejects_incomplete_bodies() {
+                // The client hung up before sending all of it
+                for req in [
+                    b"PUT /files/a HTTP/1.1\r\nHost: localhost\r\nContent-Length: 100\r\n\r\nHi!".as_slice(),
+                    b"PUT /files/a HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n64\r\nHi!",
+                    b"PUT /files/a HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nHi!\r\n",
+                ] {
+                    assert_rejected(req, true);
+                    assert_rejected(req, false);
+                }
+            }
+
+            #[test]
+            fn closes_after_http_1_0_transfer_encoding() {
+                let req = b"POST /files/c HTTP/1.0\r\nConnection: keep-alive\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nHi!\r\n0\r\n\r\nGET /echo/second HTTP/1.0\r\nConnection: keep-alive\r\n\r\n";
+                let (connection, written) = route(req, false);
+                assert_eq!(Connection::Close, connection);
+                assert!(written.starts_with("HTTP/1.1 201 Created\r\n"), "{written}");
+                assert!(written.contains("\r\nConnection: close\r\n"));
+                assert_rejected(req, true);
+            }
+
+            #[test]
+            fn rejects_malformed_request_lines() {
+                for req in [
+                    b"GET  / HTTP/1.1\r\nHost: localhost\r\n\r\n".as_slice(),
+                    b"GET /\tHTTP/1.1\r\nHost: localhost\r\n\r\n",
+                    b"GET / HTTP/1.1 \r\n\r\n",
+                    b"GET / HTTP/1.1 extra\r\n\r\n",
+                    b"GET /\x7f HTTP/1.1\r\nHost: localhost\r\n\r\n",
+                    b"GET / HTTP/1.x\r\n\r\n",
+                    b"GET / FOO/1.1\r\n\r\n",
+                    b"GET /\r\n\r\n",
+                    b"G(T / HTTP/1.1\r\nHost: localhost\r\n\r\n",
+                    b"GET echo/abc HTTP/1.1\r\nHost: localhost\r\n\r\n",
+                    b"GET * HTTP/1.1\r\nHost: localhost\r\n\r\n",
+                    b" GET / HTTP/1.1\r\nHost: localhost\r\n\r\n",
+                ] {
+                    assert_rejected(req, true);
+                }
+                for req in [b"GET".as_slice(), b"\r\n", b"GET\r\n\r\n"] {
+                    let req = [req, b"\r\n"].concat();
+                    assert_rejected(&req, true);
+                    assert_rejected(&req, false);
+                }
+            }
+
+            #[test]
+            fn accepts_well_formed_requests() {
+                for req in [
+                    b"GET /echo/abc HTTP/1.1\r\nHost: localhost\r\n\r\n".as_slice(),
+                    b"GET http://localhost:4221/echo/abc?x=1 HTTP/1.1\r\nHost: localhost\r\n\r\n",
+                    b"OPTIONS * HTTP/1.1\r\nHost: localhost\r\n\r\n",
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nContent-Length: 3\r\n\r\nHi!",
+                    b"POST /files/a HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n3;ext=1\r\nHi!\r\n0\r\nX-Trailer: 1\r\n\r\n",
+                ] {
+                    let (connection, written) = route(req, true);
+                    assert_eq!(Connection::KeepAlive, connection, "{req:?}");
+                    assert!(written.starts_with("HTTP/1.1 2"), "{req:?}: {written}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,388 @@
+use crate::{
+    http::{Method, Request, Response},
+    Result,
+};
+
+// What a route runs. It gets the request, with the parameters of the pattern
+// filled in, and the directory files are served from.
+pub type Endpoint<T> = fn(&Request, &T) -> Result<Response>;
+
+// Each parameter's name and the value the path gave it
+type Params = Vec<(String, String)>;
+
+// The order methods are listed in, in an Allow header
+const METHODS: [Method; 6] = [
+    Method::Get,
+    Method::Head,
+    Method::Post,
+    Method::Put,
+    Method::Delete,
+    Method::Options,
+];
+
+// A segment of a pattern, one of
+// - `echo`, which only matches itself
+// - `{msg}`, which matches any one segment
+// - `{*path}`, which matches the rest of the path, one segment or more
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Static(String),
+    Param(String),
+    Wildcard(String),
+}
+
+impl Segment {
+    fn parse(s: &str) -> Option<Self> {
+        let segment = match s.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+            Some(name) => match name.strip_prefix('*') {
+                Some(name) => Self::Wildcard(name.to_owned()),
+                None => Self::Param(name.to_owned()),
+            },
+            None => Self::Static(s.to_owned()),
+        };
+        match &segment {
+            Self::Static(s) if s.is_empty() || s.contains(['{', '}']) => None,
+            Self::Param(name) | Self::Wildcard(name)
+                if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') =>
+            {
+                None
+            }
+            _ => Some(segment),
+        }
+    }
+
+    // Lower is more specific
+    fn rank(&self) -> u8 {
+        match self {
+            Self::Static(_) => 0,
+            Self::Param(_) => 1,
+            Self::Wildcard(_) => 2,
+        }
+    }
+
+    // Matches the same paths, whatever the parameters are called
+    fn same_shape(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Static(a), Self::Static(b)) => a == b,
+            _ => self.rank() == other.rank(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Pattern {
+    segments: Vec<Segment>,
+}
+
+impl Pattern {
+    // "/" on its own, or segments each following a "/". A wildcard can only
+    // be the last segment, and a parameter name can only be used once.
+    fn parse(pattern: &str) -> Option<Self> {
+        let segments = match pattern.strip_prefix('/')? {
+            "" => Vec::new(),
+            rest => rest
+                .split('/')
+                .map(Segment::parse)
+                .collect::<Option<Vec<_>>>()?,
+        };
+        let mut names = Vec::new();
+        for (i, segment) in segments.iter().enumerate() {
+            match segment {
+                Segment::Wildcard(_) if i != segments.len() - 1 => return None,
+                Segment::Param(name) | Segment::Wildcard(name) if names.contains(&name) => {
+                    return None
+                }
+                Segment::Param(name) | Segment::Wildcard(name) => names.push(name),
+                Segment::Static(_) => {}
+            }
+        }
+        Some(Self { segments })
+    }
+
+    // The parameters, if the (decoded) path matches. A wildcard's value is
+    // the rest of the path, joined with "/".
+    fn matches(&self, path_parts: &[String]) -> Option<Params> {
+        let mut params = Vec::new();
+        let mut parts = path_parts.iter();
+        for segment in &self.segments {
+            match segment {
+                Segment::Static(s) => {
+                    if parts.next()? != s {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => params.push((name.to_owned(), parts.next()?.to_owned())),
+                Segment::Wildcard(name) => {
+                    let rest = parts.by_ref().map(String::as_str).collect::<Vec<&str>>();
+                    if rest.is_empty() {
+                        return None;
+                    }
+                    params.push((name.to_owned(), rest.join("/")));
+                }
+            }
+        }
+        parts.next().is_none().then_some(params)
+    }
+
+    // Compared segment by segment, so the first place two patterns differ
+    // decides which is more specific: static over parameter over wildcard.
+    // Two patterns that match the same path can't rank the same unless they
+    // are the same shape, which `Routes::add` doesn't allow, so there is
+    // always exactly one winner.
+    fn precedence(&self) -> Vec<u8> {
+        self.segments.iter().map(Segment::rank).collect()
+    }
+
+    fn same_shape(&self, other: &Self) -> bool {
+        self.segments.len() == other.segments.len()
+            && self
+                .segments
+                .iter()
+                .zip(&other.segments)
+                .all(|(a, b)| a.same_shape(b))
+    }
+}
+
+#[derive(Debug)]
+pub struct Route<T> {
+    pattern: Pattern,
+    endpoints: Vec<(Method, Endpoint<T>)>,
+}
+
+impl<T> Route<T> {
+    // HEAD is answered like GET, unless it has its own endpoint
+    pub fn endpoint(&self, method: Method) -> Option<Endpoint<T>> {
+        let find = |method| {
+            self.endpoints
+                .iter()
+                .find(|(m, _)| *m == method)
+                .map(|(_, endpoint)| *endpoint)
+        };
+        match (find(method), method) {
+            (None, Method::Head) => find(Method::Get),
+            (endpoint, _) => endpoint,
+        }
+    }
+
+    // What goes in the Allow header of a 405 or an OPTIONS response. OPTIONS
+    // is always allowed, the router answers it for every route.
+    pub fn methods(&self) -> Vec<Method> {
+        METHODS
+            .into_iter()
+            .filter(|&method| method == Method::Options || self.endpoint(method).is_some())
+            .collect()
+    }
+}
+
+// The route table. Paths are matched against the patterns of every route,
+// and the most specific match wins (see Pattern::precedence), whatever order
+// they were added in.
+#[derive(Debug)]
+pub struct Routes<T> {
+    routes: Vec<Route<T>>,
+}
+
+impl<T> Routes<T> {
+    pub fn new() -> Self {
+        Self { routes: Vec::new() }
+    }
+
+    // Patterns are written in code, so one that doesn't parse, or a method
+    // registered twice for the same paths, is a bug and panics straight away
+    pub fn add(mut self, methods: &[Method], pattern: &str, endpoint: Endpoint<T>) -> Self {
+        let parsed =
+            Pattern::parse(pattern).unwrap_or_else(|| panic!("Invalid route pattern: {pattern}"));
+        let i = match self
+            .routes
+            .iter()
+            .position(|r| r.pattern.same_shape(&parsed))
+        {
+            Some(i) if self.routes[i].pattern.segments == parsed.segments => i,
+            Some(_) => panic!("Route pattern {pattern} names its parameters differently"),
+            None => {
+                self.routes.push(Route {
+                    pattern: parsed,
+                    endpoints: Vec::new(),
+                });
+                self.routes.len() - 1
+            }
+        };
+        let route = &mut self.routes[i];
+        for &method in methods {
+            if route.endpoints.iter().any(|(m, _)| *m == method) {
+                panic!("{method} {pattern} is already routed");
+            }
+            route.endpoints.push((method, endpoint));
+        }
+        self
+    }
+
+    // The route for the path, and the parameters taken from it
+    pub fn find(&self, path_parts: &[String]) -> Option<(&Route<T>, Params)> {
+        self.routes
+            .iter()
+            .filter_map(|route| Some((route, route.pattern.matches(path_parts)?)))
+            .min_by_key(|(route, _)| route.pattern.precedence())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod routes {
+        use crate::http::{Method, Request, Response, StatusCode};
+        use crate::router::routes::{Pattern, Routes};
+        use crate::Result;
+        use std::io::BufReader;
+
+        fn parts(path: &str) -> Vec<String> {
+            path.split('/')
+                .filter(|s| !s.is_empty())
+                .map(str::to_owned)
+                .collect()
+        }
+
+        fn params(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect()
+        }
+
+        fn ok(_req: &Request, _dir: &()) -> Result<Response> {
+            Response::ok()
+        }
+
+        fn no_content(_req: &Request, _dir: &()) -> Result<Response> {
+            Response::no_content()
+        }
+
+        #[test]
+        fn parses_patterns() {
+            for pattern in ["/", "/echo", "/echo/{msg}", "/files/{*path}", "/{a}/{b}"] {
+                assert!(Pattern::parse(pattern).is_some(), "{pattern}");
+            }
+            for pattern in [
+                "",
+                "echo",
+                "/echo/",
+                "//echo",
+                "/{}",
+                "/{*}",
+                "/{a b}",
+                "/a{b}",
+                "/{*rest}/a",
+                "/{a}/{a}",
+            ] {
+                assert!(Pattern::parse(pattern).is_none(), "{pattern}");
+            }
+        }
+
+        #[test]
+        fn matches_paths() {
+            let pattern = Pattern::parse("/echo/{msg}").unwrap();
+            assert_eq!(
+                Some(params(&[("msg", "a b")])),
+                pattern.matches(&["echo".to_owned(), "a b".to_owned()])
+            );
+            assert_eq!(None, pattern.matches(&parts("/echo")));
+            assert_eq!(None, pattern.matches(&parts("/echo/a/b")));
+            assert_eq!(None, pattern.matches(&parts("/files/a")));
+
+            let pattern = Pattern::parse("/files/{*path}").unwrap();
+            assert_eq!(
+                Some(params(&[("path", "a/b/c")])),
+                pattern.matches(&parts("/files/a/b/c"))
+            );
+            assert_eq!(None, pattern.matches(&parts("/files")));
+
+            let pattern = Pattern::parse("/").unwrap();
+            assert_eq!(Some(Vec::new()), pattern.matches(&[]));
+            assert_eq!(None, pattern.matches(&parts("/a")));
+        }
+
+        #[test]
+        fn prefers_static_over_param_over_wildcard() {
+            // Added least specific first, to show the order doesn't matter
+            let routes = Routes::new()
+                .add(&[Method::Get], "/{*path}", ok)
+                .add(&[Method::Get], "/files/{*path}", ok)
+                .add(&[Method::Get], "/files/{name}", ok)
+                .add(&[Method::Get], "/files/index", ok)
+                .add(&[Method::Get], "/{dir}/index", ok);
+            let find = |path| {
+                routes
+                    .find(&parts(path))
+                    .map(|(route, params)| (route.pattern.precedence(), params))
+            };
+            assert_eq!(Some((vec![0, 0], Vec::new())), find("/files/index"));
+            assert_eq!(
+                Some((vec![0, 1], params(&[("name", "a")]))),
+                find("/files/a")
+            );
+            assert_eq!(
+                Some((vec![0, 2], params(&[("path", "a/b")]))),
+                find("/files/a/b")
+            );
+            assert_eq!(
+                Some((vec![1, 0], params(&[("dir", "docs")]))),
+                find("/docs/index")
+            );
+            assert_eq!(Some((vec![2], params(&[("path", "docs")]))), find("/docs"));
+            assert!(find("/").is_none());
+        }
+
+        fn request() -> Request {
+            let mut req = b"GET / HTTP/1.0\r\n\r\n".as_slice();
+            Request::try_from(&mut BufReader::new(&mut req)).unwrap()
+        }
+
+        #[test]
+        fn filters_methods() {
+            let routes = Routes::new()
+                .add(&[Method::Get, Method::Put], "/files/{name}", ok)
+                .add(&[Method::Delete], "/files/{name}", no_content);
+            let (route, _) = routes.find(&parts("/files/a")).unwrap();
+            assert_eq!(
+                vec![
+                    Method::Get,
+                    Method::Head,
+                    Method::Put,
+                    Method::Delete,
+                    Method::Options
+                ],
+                route.methods()
+            );
+            let status = |method| {
+                route
+                    .endpoint(method)
+                    .map(|endpoint| endpoint(&request(), &()).unwrap().status_code())
+            };
+            // HEAD goes where GET does
+            assert_eq!(Some(StatusCode::Ok), status(Method::Head));
+            assert_eq!(Some(StatusCode::NoContent), status(Method::Delete));
+            assert_eq!(None, status(Method::Post));
+            assert_eq!(None, status(Method::Options));
+        }
+
+        #[test]
+        #[should_panic(expected = "already routed")]
+        fn refuses_the_same_route_twice() {
+            let _ = Routes::new().add(&[Method::Get], "/echo/{msg}", ok).add(
+                &[Method::Get, Method::Post],
+                "/echo/{msg}",
+                ok,
+            );
+        }
+
+        #[test]
+        #[should_panic(expected = "names its parameters differently")]
+        fn refuses_ambiguous_patterns() {
+            let _ = Routes::new().add(&[Method::Get], "/echo/{msg}", ok).add(
+                &[Method::Post],
+                "/echo/{text}",
+                ok,
+            );
+        }
+    }
+}
@@ -1,8 +1,10 @@
-use super::ThreadPool;
+use super::{ThreadPool, TimedStream, Timeouts};
 use crate::dir::Dir;
+use crate::http::{Connection, KeepAlive, RequestLimits};
 use crate::router::Router;
 use crate::{Config, Result};
-use std::net::TcpListener;
+use std::io::BufReader;
+use std::net::{TcpListener, TcpStream};
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
@@ -16,6 +18,8 @@ pub struct Server {
     router: Arc<Router<Dir>>,
     thread_pool: ThreadPool,
     running: Arc<AtomicBool>,
+    timeouts: Timeouts,
+    max_requests: usize,
 }
 
 impl Server {
@@ -24,7 +28,19 @@ impl Server {
         listener.set_nonblocking(true)?;
         // I feel like trying to get rid of this clone would be overkill...
         // Clippy isn't annoyed with me about this
-        let router: Arc<Router<Dir>> = Arc::new(Router::new(config.directory.clone()));
+        let router: Arc<Router<Dir>> = Arc::new(
+            Router::new(config.directory.clone())
+                .send_date(config.send_date)
+                .send_server(config.send_server)
+                .decode_limit(config.max_decoded_body)
+                .limits(RequestLimits {
+                    request_line: config.max_request_line,
+                    header_size: config.max_header_size,
+                    header_count: config.max_headers,
+                    body: config.max_body,
+                })
+                .strict(config.strict),
+        );
         // TODO: put this in config?
         let thread_pool = ThreadPool::new(8);
         let running = Arc::new(AtomicBool::new(true));
@@ -33,6 +49,14 @@ impl Server {
             router,
             thread_pool,
             running,
+            timeouts: Timeouts {
+                idle: config.keep_alive_timeout,
+                header: config.header_timeout,
+                body: config.body_timeout,
+                write: config.write_timeout,
+                min_data_rate: config.min_data_rate,
+            },
+            max_requests: config.max_requests,
         })
     }
     pub fn start(&self) -> Result<()> {
@@ -49,11 +73,14 @@ impl Server {
                 Ok((stream, addr)) => {
                     info!("Connection from: {}", addr);
                     let router: Arc<Router<Dir>> = Arc::clone(&self.router);
+                    let timeouts = self.timeouts;
+                    let max_requests = self.max_requests;
+                    // The worker owns the connection until it is closed
                     self.thread_pool.execute(move || {
-                        if let Err(e) = router.route(&stream) {
-                            error!("Error handling request, {}", e);
+                        if let Err(e) = handle_connection(&router, stream, timeouts, max_requests) {
+                            error!("Error handling connection, {}", e);
                         } else {
-                            info!("Request handled OK");
+                            info!("Connection from {} closed", addr);
                         }
                     })?;
                 }
@@ -76,3 +103,34 @@ impl Server {
         Ok(())
     }
 }
+
+fn handle_connection(
+    router: &Router<Dir>,
+    stream: TcpStream,
+    timeouts: Timeouts,
+    max_requests: usize,
+) -> Result<()> {
+    // Some platforms hand out accepted sockets with the listener's
+    // non-blocking flag, but we want to block (up to the timeouts)
+    stream.set_nonblocking(false)?;
+    // A client that stops reading the response holds up the write
+    stream.set_write_timeout(Some(timeouts.write))?;
+
+    // One reader for the whole connection, so bytes of pipelined requests
+    // buffered while reading an earlier one are not thrown away. Requests are
+    // handled one at a time, so responses go out in the order they came in.
+    let mut reader = BufReader::new(TimedStream::new(&stream, timeouts));
+    let mut writer = &stream;
+
+    for served in 1..=max_requests {
+        let keep_alive = (served < max_requests).then_some(KeepAlive {
+            timeout: timeouts.idle,
+            max: max_requests - served,
+        });
+        if router.route(&mut reader, &mut writer, keep_alive)? == Connection::Close {
+            break;
+        }
+        info!("Request handled OK");
+    }
+    Ok(())
+}
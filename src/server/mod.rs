@@ -1,5 +1,7 @@
 mod app_server;
 mod thread_pool;
+mod timeouts;
 
 pub use app_server::Server;
 use thread_pool::ThreadPool;
+pub use timeouts::{Phase, Timed, TimedStream, Timeouts};
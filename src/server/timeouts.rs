@@ -0,0 +1,218 @@
+use std::{
+    io::{Error, ErrorKind, Read},
+    net::TcpStream,
+    time::{Duration, Instant},
+};
+
+// A client is held to the minimum data rate only once it has had this long
+const MIN_DATA_RATE_GRACE: Duration = Duration::from_secs(1);
+
+// How long we wait on a client at each point of a request
+#[derive(Debug, Clone, Copy)]
+pub struct Timeouts {
+    // For the next request to start on a persistent connection
+    pub idle: Duration,
+    // For the whole head, once the request has started
+    pub header: Duration,
+    // For each read of the body
+    pub body: Duration,
+    // For each write of the response
+    pub write: Duration,
+    // Bytes a second, on average, while reading the head or the body. Zero
+    // turns it off.
+    pub min_data_rate: u64,
+}
+
+// Where the router is in reading a request
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    Idle,
+    Head,
+    Body,
+}
+
+// A reader the router can tell which phase it is in, so that the right
+// timeout applies
+pub trait Timed {
+    fn start(&mut self, phase: Phase);
+}
+
+// Reads from the client, failing with TimedOut when the client takes longer
+// than the current phase allows or sends so slowly it falls below the
+// minimum data rate. A client trickling a byte at a time (slowloris) would
+// otherwise keep a worker busy without ever going over a read timeout.
+#[derive(Debug)]
+pub struct TimedStream<'a> {
+    stream: &'a TcpStream,
+    timeouts: Timeouts,
+    phase: Phase,
+    started: Instant,
+    read: u64,
+}
+
+impl<'a> TimedStream<'a> {
+    pub fn new(stream: &'a TcpStream, timeouts: Timeouts) -> Self {
+        Self {
+            stream,
+            timeouts,
+            phase: Phase::Idle,
+            started: Instant::now(),
+            read: 0,
+        }
+    }
+
+    // The latest the next read may finish. A timeout too long to add to the
+    // clock (-k 18446744073709551615) is as good as none, so there is no
+    // deadline then.
+    fn deadline(&self) -> Option<Instant> {
+        let deadline = match self.phase {
+            Phase::Idle => self.started.checked_add(self.timeouts.idle),
+            Phase::Head => self.started.checked_add(self.timeouts.header),
+            Phase::Body => Instant::now().checked_add(self.timeouts.body),
+        };
+        if self.phase == Phase::Idle || self.timeouts.min_data_rate == 0 {
+            return deadline;
+        }
+        // When what has been read so far stops being enough
+        let enough_for =
+            Duration::from_secs_f64(self.read as f64 / self.timeouts.min_data_rate as f64);
+        let too_slow = self
+            .started
+            .checked_add(enough_for.max(MIN_DATA_RATE_GRACE));
+        match (deadline, too_slow) {
+            (Some(deadline), Some(too_slow)) => Some(deadline.min(too_slow)),
+            (deadline, too_slow) => deadline.or(too_slow),
+        }
+    }
+}
+
+impl Timed for TimedStream<'_> {
+    fn start(&mut self, phase: Phase) {
+        self.phase = phase;
+        self.started = Instant::now();
+        self.read = 0;
+    }
+}
+
+impl Read for TimedStream<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let timeout = match self.deadline() {
+            Some(deadline) => {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                // A zero timeout would mean no timeout at all
+                if timeout.is_zero() {
+                    return Err(ErrorKind::TimedOut.into());
+                }
+                Some(timeout)
+            }
+            None => None,
+        };
+        self.stream.set_read_timeout(timeout)?;
+        let read = self.stream.read(buf).map_err(|e| match e.kind() {
+            // Which of these a timeout is depends on the platform
+            ErrorKind::WouldBlock => Error::from(ErrorKind::TimedOut),
+            _ => e,
+        })?;
+        self.read += read as u64;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    mod timeouts {
+        use crate::server::timeouts::{Phase, Timed, TimedStream, Timeouts};
+        use std::{
+            io::{ErrorKind, Read, Write},
+            net::{TcpListener, TcpStream},
+            thread,
+            time::{Duration, Instant},
+        };
+
+        const TIMEOUTS: Timeouts = Timeouts {
+            idle: Duration::from_millis(200),
+            header: Duration::from_secs(3),
+            body: Duration::from_millis(200),
+            write: Duration::from_secs(1),
+            min_data_rate: 100,
+        };
+
+        // A client that sends `chunks`, waiting `gap` before each one
+        fn connect(chunks: Vec<&'static [u8]>, gap: Duration) -> TcpStream {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            thread::spawn(move || {
+                let mut client = TcpStream::connect(addr).unwrap();
+                for chunk in chunks {
+                    thread::sleep(gap);
+                    if client.write_all(chunk).is_err() {
+                        break;
+                    }
+                }
+                thread::sleep(Duration::from_secs(5));
+            });
+            listener.accept().unwrap().0
+        }
+
+        #[test]
+        fn times_out_idle_connections() {
+            let stream = connect(Vec::new(), Duration::ZERO);
+            let mut reader = TimedStream::new(&stream, TIMEOUTS);
+            let started = Instant::now();
+            let err = reader.read(&mut [0; 16]).unwrap_err();
+            assert_eq!(ErrorKind::TimedOut, err.kind());
+            assert!(started.elapsed() < Duration::from_secs(1));
+        }
+
+        #[test]
+        fn disconnects_trickling_clients() {
+            let stream = connect(
+                vec![b"GET".as_slice(), b" ", b"/", b" ", b"H"],
+                Duration::from_millis(400),
+            );
+            let mut reader = TimedStream::new(&stream, TIMEOUTS);
+            reader.start(Phase::Head);
+            let started = Instant::now();
+            let mut head = Vec::new();
+            let err = reader.read_to_end(&mut head).unwrap_err();
+            assert_eq!(ErrorKind::TimedOut, err.kind());
+            // Well before the header timeout
+            assert!(started.elapsed() < Duration::from_secs(2));
+            assert!(!head.is_empty());
+        }
+
+        #[test]
+        fn keeps_clients_that_keep_up() {
+            let stream = connect(
+                vec![b"0123456789".as_slice(); 12],
+                Duration::from_millis(50),
+            );
+            let mut reader = TimedStream::new(&stream, TIMEOUTS);
+            reader.start(Phase::Body);
+            let mut body = [0; 120];
+            reader.read_exact(&mut body).unwrap();
+        }
+
+        #[test]
+        fn waits_without_deadline_for_endless_timeouts() {
+            let stream = connect(vec![b"GET / HTTP/1.1\r\n".as_slice()], Duration::ZERO);
+            let mut reader = TimedStream::new(
+                &stream,
+                Timeouts {
+                    idle: Duration::MAX,
+                    header: Duration::MAX,
+                    body: Duration::MAX,
+                    write: Duration::MAX,
+                    min_data_rate: 0,
+                },
+            );
+            for phase in [Phase::Idle, Phase::Head, Phase::Body] {
+                reader.start(phase);
+                assert_eq!(None, reader.deadline());
+            }
+            let mut line = [0; 16];
+            reader.read_exact(&mut line).unwrap();
+        }
+    }
+}